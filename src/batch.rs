@@ -0,0 +1,205 @@
+/*!
+Sending many requests at once with a bound on how many go to FRED together.
+*/
+
+use {
+    crate::{FredClient, Lookup, RequestSpec, Result},
+    sled::IVec,
+    std::collections::VecDeque,
+    tokio::task::JoinSet,
+};
+
+const DEFAULT_CONCURRENCY: usize = 4;
+
+/**
+The responses to a set of requests, see [`FredClient::batch`]. Requests the cache can
+answer are answered before any request goes to FRED, and at most ``concurrency`` of the
+rest are sent at once. Each request has its own result, so one failure does not end the
+batch. Requests still in flight are abandoned if the batch is dropped. With
+``Lookup::StaleWhileRevalidate``, stale entries are answered from the cache too and
+refreshed in the background, outside the ``concurrency`` bound.
+*/
+#[derive(Debug)]
+pub struct Batch {
+    client: FredClient,
+    lookup: Lookup,
+    concurrency: usize,
+    ready: VecDeque<(usize, Result<IVec>)>,
+    // Stale entries to refresh once the batch is first polled inside a runtime.
+    stale: Vec<RequestSpec>,
+    misses: VecDeque<(usize, RequestSpec)>,
+    running: JoinSet<(usize, Result<IVec>)>,
+    len: usize,
+}
+
+impl Batch {
+    pub(crate) fn new(client: FredClient, reqs: Vec<RequestSpec>, lookup: Lookup) -> Self {
+        let len = reqs.len();
+        let mut ready = VecDeque::new();
+        let mut misses = VecDeque::new();
+        let mut stale = Vec::new();
+        for (i, req) in reqs.into_iter().enumerate() {
+            match client.cached(&req, lookup) {
+                Ok(Some((bytes, false))) => ready.push_back((i, Ok(bytes))),
+                Ok(Some((bytes, true))) => {
+                    ready.push_back((i, Ok(bytes)));
+                    stale.push(req);
+                },
+                Ok(None) => misses.push_back((i, req)),
+                Err(e) => ready.push_back((i, Err(e))),
+            }
+        }
+        Batch {
+            client,
+            lookup,
+            concurrency: DEFAULT_CONCURRENCY,
+            ready,
+            stale,
+            misses,
+            running: JoinSet::new(),
+            len,
+        }
+    }
+
+    /**
+    At most ``concurrency`` requests go to FRED at once, at least one and four by
+    default. The client's rate limiter still paces them.
+    */
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    /**
+    The number of requests in the batch.
+    */
+    pub fn len(&self) -> usize { self.len }
+
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /**
+    The next response to arrive with the position of its request, or ``None`` once
+    every request has been answered.
+    */
+    // test: batch_answers_every_request
+    pub async fn next(&mut self) -> Option<(usize, Result<IVec>)> {
+        for req in self.stale.drain(..) {
+            self.client.revalidate(&req);
+        }
+        if let Some(done) = self.ready.pop_front() {
+            return Some(done);
+        }
+        while self.running.len() < self.concurrency {
+            let Some((i, req)) = self.misses.pop_front() else { break };
+            let client = self.client.clone();
+            let lookup = self.lookup;
+            self.running.spawn(async move { (i, client.send(&req, lookup).await) });
+        }
+        match self.running.join_next().await? {
+            Ok(done) => Some(done),
+            Err(e) => std::panic::resume_unwind(e.into_panic()),
+        }
+    }
+
+    /**
+    Every response, in the order of the requests.
+    */
+    // test: batch_answers_every_request
+    pub async fn collect(mut self) -> Vec<Result<IVec>> {
+        let mut results: Vec<Option<Result<IVec>>> = (0..self.len).map(|_| None).collect();
+        while let Some((i, result)) = self.next().await {
+            results[i] = Some(result);
+        }
+        results.into_iter().flatten().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::{testing::MockServer, *},
+        std::collections::HashSet,
+    };
+
+    #[tokio::test]
+    async fn batch_answers_every_request() {
+        let server = MockServer::start().await.unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let client = FredClient::new(sled::open(dir.path()).unwrap(), Some("abcd")).unwrap()
+            .with_base_uri(&server.base_uri()).unwrap()
+            .allow_http(true).unwrap()
+            .with_rate_limit(None)
+            .with_retry(RetryPolicy::none());
+
+        let ids = ["A", "B", "C", "D", "E", "F"];
+        for id in ids {
+            server.insert(&format!("series?series_id={id}&"), 200, format!("<series id=\"{id}\"/>"));
+        }
+        server.insert_error("series?series_id=BAD&", 400, "Bad Request.");
+        let mut reqs: Vec<RequestSpec> = ids
+            .iter()
+            .map(|id| client.request(&format!("series?series_id={id}&")).unwrap())
+            .collect();
+        reqs.insert(2, client.request("series?series_id=BAD&").unwrap());
+        let entry = CacheEntry { body: "cached".into(), meta: None };
+        client.store().put_entry(&reqs[5].ivec(), &entry, false).unwrap();
+
+        let results = client.batch(reqs.clone(), Lookup::FredOnCacheMiss)
+            .with_concurrency(2)
+            .collect()
+            .await;
+        assert_eq!(results.len(), 7);
+        assert!(matches!(results[2], Err(Error::FredApi { status: 400, .. })));
+        assert_eq!(*results[0].as_ref().unwrap(), r#"<series id="A"/>"#);
+        assert_eq!(*results[5].as_ref().unwrap(), "cached");
+        assert_eq!(server.hits(), 6);
+
+        // Cache hits come first, and every request is answered once.
+        let mut batch = client.batch(reqs, Lookup::CacheOnly);
+        assert_eq!(batch.next().await.unwrap().0, 0);
+        let mut seen = HashSet::from([0]);
+        while let Some((i, result)) = batch.next().await {
+            assert!(seen.insert(i));
+            assert_eq!(result.is_err(), i == 2);
+        }
+        assert_eq!(seen.len(), 7);
+        assert_eq!(server.hits(), 6);
+    }
+
+    #[tokio::test]
+    async fn batch_returns_stale_entries_without_waiting() {
+        let server = MockServer::start().await.unwrap();
+        let dir = tempfile::TempDir::new().unwrap();
+        let client = FredClient::new(sled::open(dir.path()).unwrap(), Some("abcd")).unwrap()
+            .with_base_uri(&server.base_uri()).unwrap()
+            .allow_http(true).unwrap()
+            .with_rate_limit(None)
+            .with_retry(RetryPolicy::none());
+        for id in ["A", "B"] {
+            server.insert(&format!("series?series_id={id}&"), 200, format!("<series id=\"{id}\"/>"));
+        }
+        let reqs: Vec<RequestSpec> = ["A", "B"]
+            .iter()
+            .map(|id| client.request(&format!("series?series_id={id}&")).unwrap())
+            .collect();
+        // An entry without metadata is stale.
+        let entry = CacheEntry { body: "stale".into(), meta: None };
+        client.store().put_entry(&reqs[1].ivec(), &entry, false).unwrap();
+
+        // The stale entry is answered before the miss ahead of it is fetched.
+        let mut batch = client.batch(reqs, Lookup::StaleWhileRevalidate).with_concurrency(1);
+        let (i, result) = batch.next().await.unwrap();
+        assert_eq!((i, result.unwrap()), (1, "stale".into()));
+        let (i, result) = batch.next().await.unwrap();
+        assert_eq!((i, result.unwrap()), (0, r#"<series id="A"/>"#.into()));
+        assert!(batch.next().await.is_none());
+
+        // The stale entry is refreshed in the background.
+        for _ in 0..100 {
+            if server.hits() == 2 { break }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.hits(), 2);
+        assert_eq!(server.requests().iter().filter(|r| r.contains("=B&")).count(), 1);
+    }
+}
@@ -0,0 +1,226 @@
+/*!
+Cache entries and the metadata recorded with each FRED response.
+*/
+
+use {
+    crate::{src, CacheStore, DebugErr, Error, Format, RequestSpec, Result},
+    sled::IVec,
+    std::{
+        fmt::Write,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    },
+};
+
+/**
+What was known about a response when it was cached.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryMeta {
+    pub fetched_at: SystemTime,
+    /// HTTP status of the response.
+    pub status: u16,
+    pub last_modified: Option<String>,
+    pub etag: Option<String>,
+    /// Length of the body in bytes.
+    pub length: u64,
+    /// Version of ``fred_api`` that fetched the response.
+    pub crate_version: String,
+    /// XML for entries cached before the format was recorded.
+    pub format: Format,
+}
+
+impl EntryMeta {
+
+    /**
+    Metadata for a body fetched now by this version of the crate.
+    */
+    pub fn new(status: u16, length: u64) -> Self {
+        EntryMeta {
+            fetched_at: SystemTime::now(),
+            status,
+            last_modified: None,
+            etag: None,
+            length,
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            format: Format::Xml,
+        }
+    }
+
+    /**
+    Time since the response was fetched.
+    */
+    pub fn age(&self) -> Duration {
+        SystemTime::now().duration_since(self.fetched_at).unwrap_or(Duration::ZERO)
+    }
+
+    /**
+    Encoded as ``name=value`` lines.
+    */
+    // test: entry_meta_round_trips
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let millis = self.fetched_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let mut s = String::new();
+        let _ = writeln!(s, "fetched_at={millis}");
+        let _ = writeln!(s, "status={}", self.status);
+        if let Some(last_modified) = &self.last_modified {
+            let _ = writeln!(s, "last_modified={last_modified}");
+        }
+        if let Some(etag) = &self.etag {
+            let _ = writeln!(s, "etag={etag}");
+        }
+        let _ = writeln!(s, "length={}", self.length);
+        let _ = writeln!(s, "crate_version={}", self.crate_version);
+        let _ = writeln!(s, "format={}", self.format);
+        s.into_bytes()
+    }
+
+    // test: entry_meta_round_trips
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let s = std::str::from_utf8(bytes)
+            .map_err(|e| Error::Cache(src!("Metadata is not UTF-8: {e}")))?;
+        let mut meta = EntryMeta::new(0, 0);
+        let mut fetched_at = None;
+        for line in s.lines() {
+            let Some((name, value)) = line.split_once('=') else { continue };
+            let number = || value.parse::<u64>()
+                .map_err(|e| Error::Cache(src!("Bad metadata '{line}': {e}")));
+            match name {
+                "fetched_at" => fetched_at = Some(UNIX_EPOCH + Duration::from_millis(number()?)),
+                "status" => meta.status = number()? as u16,
+                "last_modified" => meta.last_modified = Some(value.to_string()),
+                "etag" => meta.etag = Some(value.to_string()),
+                "length" => meta.length = number()?,
+                "crate_version" => meta.crate_version = value.to_string(),
+                "format" => meta.format = value
+                    .parse()
+                    .map_err(|_| Error::Cache(src!("Bad metadata '{line}'")))?,
+                _ => {},
+            }
+        }
+        meta.fetched_at = fetched_at
+            .ok_or(Error::Cache(src!("Metadata has no 'fetched_at'")))?;
+        Ok(meta)
+    }
+}
+
+/**
+A cached response body with its metadata. Entries cached before metadata was recorded
+have none.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct CacheEntry {
+    pub body: IVec,
+    pub meta: Option<EntryMeta>,
+}
+
+impl CacheEntry {
+
+    /**
+    Whether the entry was fetched at least ``age`` ago. Entries without metadata are.
+    */
+    // test: lookup_entry_returns_metadata
+    pub fn is_older_than(&self, age: Duration) -> bool {
+        self.meta.as_ref().is_none_or(|meta| meta.age() >= age)
+    }
+}
+
+/**
+Non-async request to cache only, returning the body with its metadata.
+```
+use fred_api::{build_request, lookup_entry};
+
+let dir = tempfile::TempDir::new().unwrap();
+let db = sled::open(dir.path()).unwrap();
+let req = build_request("series?series_id=GNPCA&", Some("abcd")).unwrap();
+assert!(lookup_entry(&req, &db).unwrap().is_none());
+```
+*/
+// test: lookup_entry_returns_metadata
+pub fn lookup_entry(req: &RequestSpec, store: &dyn CacheStore) -> Result<Option<CacheEntry>> {
+    store.get_entry(&req.ivec())
+}
+
+/**
+The entry replaced by the last refresh of a request, when the refresh kept it. See
+[`FredClient::with_previous_versions`](crate::FredClient::with_previous_versions).
+*/
+// test: previous_entry_keeps_replaced_value
+pub fn previous_entry(req: &RequestSpec, store: &dyn CacheStore) -> Result<Option<CacheEntry>> {
+    store.get_previous(&req.ivec())
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::*,
+        std::time::{Duration, UNIX_EPOCH},
+        tempfile::TempDir,
+    };
+
+    #[test]
+    fn entry_meta_round_trips() {
+        let meta = EntryMeta {
+            fetched_at: UNIX_EPOCH + Duration::from_millis(1_760_000_000_123),
+            status: 200,
+            last_modified: Some("Wed, 21 Oct 2015 07:28:00 GMT".to_string()),
+            etag: None,
+            length: 42,
+            crate_version: "0.2.0".to_string(),
+            format: Format::Json,
+        };
+        assert_eq!(EntryMeta::from_bytes(&meta.to_bytes()).unwrap(), meta);
+        assert!(EntryMeta::from_bytes(b"status=200\n").is_err());
+    }
+
+    #[test]
+    fn lookup_entry_returns_metadata() {
+        let dir = TempDir::new().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let req = RequestSpec::new("series?series_id=GNPCA&", Some("abcd")).unwrap();
+
+        // Entries from before metadata have none.
+        db.insert(req.ivec(), "old").unwrap();
+        let entry = lookup_entry(&req, &db).unwrap().unwrap();
+        assert!(entry.is_older_than(Duration::from_secs(1_000_000)));
+        assert_eq!((entry.body, entry.meta), ("old".into(), None));
+
+        write_to_cache(&req, b"new", &EntryMeta::new(200, 3), false, &db).unwrap();
+        let entry = lookup_entry(&req, &db).unwrap().unwrap();
+        assert!(entry.is_older_than(Duration::ZERO));
+        assert!(!entry.is_older_than(Duration::from_secs(60)));
+        let meta = entry.meta.unwrap();
+        assert_eq!(entry.body, "new");
+        assert_eq!((meta.status, meta.length), (200, 3));
+        assert_eq!(meta.crate_version, env!("CARGO_PKG_VERSION"));
+        assert!(meta.age() < Duration::from_secs(60));
+
+        // `cache_request` still returns just the bytes.
+        assert_eq!(cache_request(&req, &db).unwrap().unwrap(), "new");
+        assert!(previous_entry(&req, &db).unwrap().is_none());
+    }
+
+    #[test]
+    fn previous_entry_keeps_replaced_value() {
+        let dir = TempDir::new().unwrap();
+        let db = sled::open(dir.path()).unwrap();
+        let req = RequestSpec::new("series?series_id=GNPCA&", Some("abcd")).unwrap();
+
+        // Stored metadata keeps milliseconds.
+        let first = EntryMeta {
+            fetched_at: UNIX_EPOCH + Duration::from_millis(1_760_000_000_123),
+            ..EntryMeta::new(200, 2)
+        };
+        write_to_cache(&req, b"v1", &first, true, &db).unwrap();
+        assert!(previous_entry(&req, &db).unwrap().is_none());
+
+        let bytes = write_to_cache(&req, b"v2", &EntryMeta::new(200, 2), true, &db).unwrap();
+        assert_eq!(bytes, "v2");
+        assert_eq!(cache_request(&req, &db).unwrap().unwrap(), "v2");
+        let prev = previous_entry(&req, &db).unwrap().unwrap();
+        assert_eq!((prev.body, prev.meta), ("v1".into(), Some(first)));
+
+        // Without keeping, the previous version is left as it was.
+        write_to_cache(&req, b"v3", &EntryMeta::new(200, 2), false, &db).unwrap();
+        assert_eq!(previous_entry(&req, &db).unwrap().unwrap().body, "v1");
+    }
+}
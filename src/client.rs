@@ -0,0 +1,880 @@
+/*!
+A reusable client that holds one connection pool for all requests to FRED.
+*/
+
+use {
+    crate::{
+        cache_request, format::error_details, in_flight::InFlight, lookup_entry,
+        retry::retry_after, src, write_to_cache, Batch, CacheStore, DebugErr, EntryMeta,
+        Error, Format, Lookup, Pages, RateLimiter, RequestSpec, Result, RetryPolicy,
+        TtlPolicy, BASE_URI,
+    },
+    http::StatusCode,
+    http_body_util::{BodyExt, Empty},
+    hyper::body::Bytes,
+    hyper_rustls::{ConfigBuilderExt, HttpsConnector},
+    hyper_util::{
+        client::legacy::{connect::HttpConnector, Client},
+        rt::TokioExecutor,
+    },
+    rustls::version::TLS13,
+    sled::IVec,
+    http::{header::{HeaderMap, ETAG, LAST_MODIFIED}, uri::Uri},
+    std::{
+        env, fmt,
+        str::FromStr,
+        sync::{Arc, OnceLock},
+        time::Duration,
+    },
+    tokio::time::sleep,
+};
+
+pub(crate) type HttpClient = Client<HttpsConnector<HttpConnector>, Empty<Bytes>>;
+
+// Connection pool, rate limiter and fetches under way shared by the free functions such as
+// `send_request`.
+static SHARED_HTTP: OnceLock<HttpClient> = OnceLock::new();
+static SHARED_LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+static SHARED_IN_FLIGHT: OnceLock<InFlight> = OnceLock::new();
+
+/**
+Build a hyper client speaking TLS 1.3 with the native root certificates. Plain HTTP is
+refused unless ``allow_http`` is set.
+*/
+fn http_client(allow_http: bool) -> Result<HttpClient> {
+    let tls = rustls::ClientConfig::builder_with_protocol_versions(&[&TLS13])
+        .with_native_roots().map_err(|e| Error::Tls(src!("{e}")))?
+        .with_no_client_auth();
+
+    let builder = hyper_rustls::HttpsConnectorBuilder::new().with_tls_config(tls);
+    let https = if allow_http {
+        builder.https_or_http().enable_http1().enable_http2().build()
+    } else {
+        builder.https_only().enable_http2().build()
+    };
+
+    Ok(Client::builder(TokioExecutor::new()).build(https))
+}
+
+/**
+Owns the API key, the connection pool, the cache in a [`CacheStore`], a default
+[`Lookup`], a [`RateLimiter`], a [`RetryPolicy`] and a [`TtlPolicy`]. Cloning is cheap
+and clones share the connection pool, the cache and the rate limiter. Identical requests
+to FRED made at the same time by a client and its clones share one fetch.
+```no_run
+use fred_api::{fred_cache, FredClient, Lookup};
+
+# tokio_test::block_on(async {
+let db: sled::Db = sled::open(fred_cache(None).unwrap()).unwrap();
+let client = FredClient::new(db, None).unwrap();
+for id in ["GNPCA", "UNRATE", "CPIAUCSL"] {
+    let mid_part = format!("series/observations?series_id={id}&");
+    let bytes = client.get(&mid_part).await.unwrap();
+}
+# })
+```
+*/
+#[derive(Clone)]
+pub struct FredClient {
+    api_key: String,
+    http: HttpClient,
+    store: Arc<dyn CacheStore>,
+    lookup: Lookup,
+    base_uri: String,
+    allow_http: bool,
+    limiter: Option<RateLimiter>,
+    retry: RetryPolicy,
+    ttl: TtlPolicy,
+    keep_previous: bool,
+    in_flight: InFlight,
+}
+
+impl FredClient {
+
+    /**
+    If ``api_key`` is ``None``, checks for environment variable ``FRED_API_KEY``, else
+    uses the value provided. ``store`` is usually a ``sled::Db``. The default lookup is
+    ``Lookup::FredOnCacheMiss`` and requests to FRED are limited to 120 a minute and
+    retried with the default [`RetryPolicy`].
+    */
+    // test: client_get_uses_api_key_and_default_lookup
+    pub fn new(store: impl CacheStore + 'static, api_key: Option<&str>) -> Result<Self> {
+        let api_key = match api_key {
+            Some(key) => key.to_string(),
+            None => env::var("FRED_API_KEY")
+                .map_err(|err| {
+                    Error::Config(src!("FRED_API_KEY environment variable missing: {err}"))
+                })?,
+        };
+        Ok(FredClient {
+            api_key,
+            http: http_client(false)?,
+            store: Arc::new(store),
+            lookup: Lookup::FredOnCacheMiss,
+            base_uri: BASE_URI.to_string(),
+            allow_http: false,
+            limiter: Some(RateLimiter::default()),
+            retry: RetryPolicy::default(),
+            ttl: TtlPolicy::default(),
+            keep_previous: false,
+            in_flight: InFlight::default(),
+        })
+    }
+
+    /**
+    A client on the process-wide connection pool, used by the free functions.
+    */
+    pub(crate) fn shared(store: Arc<dyn CacheStore>) -> Result<Self> {
+        let http = match SHARED_HTTP.get() {
+            Some(http) => http,
+            None => {
+                let http = http_client(false)?;
+                SHARED_HTTP.get_or_init(|| http)
+            },
+        };
+        Ok(FredClient {
+            api_key: String::new(),
+            http: http.clone(),
+            store,
+            lookup: Lookup::FredOnCacheMiss,
+            base_uri: BASE_URI.to_string(),
+            allow_http: false,
+            limiter: Some(SHARED_LIMITER.get_or_init(RateLimiter::default).clone()),
+            retry: RetryPolicy::default(),
+            ttl: TtlPolicy::default(),
+            keep_previous: false,
+            in_flight: SHARED_IN_FLIGHT.get_or_init(InFlight::default).clone(),
+        })
+    }
+
+    /**
+    Paces requests to FRED with ``limiter``, or not at all with ``None``. Pass a clone of
+    one limiter to several clients to share it. Cache hits are never limited.
+    */
+    // test: client_rate_limits_only_cache_misses
+    pub fn with_rate_limit(mut self, limiter: Option<RateLimiter>) -> Self {
+        self.limiter = limiter;
+        self
+    }
+
+    /**
+    Sets how transient failures of requests to FRED are retried.
+    */
+    // test: fred_request_retries_transient_failures
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /**
+    Sets when cached entries become stale for ``Lookup::FredIfStale`` and
+    ``Lookup::StaleWhileRevalidate``.
+    */
+    // test: client_stale_while_revalidate_refreshes_in_background
+    pub fn with_ttl(mut self, ttl: TtlPolicy) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /**
+    Keeps the entry replaced when a request is refreshed from FRED as the previous
+    version, readable with [`previous_entry`](crate::previous_entry).
+    */
+    // test: fred_only_replaces_cached_value
+    pub fn with_previous_versions(mut self, keep_previous: bool) -> Self {
+        self.keep_previous = keep_previous;
+        self
+    }
+
+    /**
+    Sets the lookup used by [`FredClient::get`].
+    */
+    pub fn with_lookup(mut self, lookup: Lookup) -> Self {
+        self.lookup = lookup;
+        self
+    }
+
+    /**
+    Sends requests to ``base_uri`` instead of [`BASE_URI`], for example a local server
+    replaying FRED responses. An ``http`` base also needs [`FredClient::allow_http`].
+    */
+    // test: client_fetches_from_local_server
+    pub fn with_base_uri(mut self, base_uri: &str) -> Result<Self> {
+        let uri = Uri::from_str(base_uri)
+            .map_err(|e| Error::InvalidUri(src!("Invalid base Uri: {e}")))?;
+        match uri.scheme_str() {
+            Some("https") | Some("http") => {},
+            _ => Err(Error::InvalidUri(src!(
+                "Base Uri '{base_uri}' must start with 'https://' or 'http://'"
+            )))?,
+        }
+        self.base_uri = base_uri.trim_end_matches('/').to_string();
+        Ok(self)
+    }
+
+    /**
+    Allows plain HTTP, which is refused by default. This rebuilds the connection pool.
+    */
+    // test: client_refuses_http_by_default
+    pub fn allow_http(mut self, allow_http: bool) -> Result<Self> {
+        if allow_http != self.allow_http {
+            self.http = http_client(allow_http)?;
+            self.allow_http = allow_http;
+        }
+        Ok(self)
+    }
+
+    pub fn base_uri(&self) -> &str { &self.base_uri }
+
+    pub fn lookup(&self) -> Lookup { self.lookup }
+
+    pub fn store(&self) -> &dyn CacheStore { &*self.store }
+
+    /**
+    Build a request from the mid-part of the URL using the client's API key.
+    */
+    pub fn request(&self, mid_part: &str) -> Result<RequestSpec> {
+        RequestSpec::new(mid_part, Some(&self.api_key))
+    }
+
+    /**
+    Send a request using the client's API key and default lookup.
+    */
+    // test: client_get_uses_api_key_and_default_lookup
+    pub async fn get(&self, mid_part: &str) -> Result<IVec> {
+        self.send(&self.request(mid_part)?, self.lookup).await
+    }
+
+    /**
+    The pages of a request to an endpoint with ``limit`` and ``offset``, such as
+    ``series/search`` or ``tags/series``. Each page goes through [`send`](Self::send).
+    ```no_run
+    # tokio_test::block_on(async {
+    use fred_api::{FredClient, Lookup};
+
+    let client = FredClient::new(sled::open("cache").unwrap(), None).unwrap();
+    let req = client.request("tags/series?tag_names=slovenia;food&").unwrap();
+    let ids = client.pages(&req, Lookup::FredOnCacheMiss)
+        .rows("series", vec!["id"])
+        .try_collect()
+        .await
+        .unwrap();
+    # });
+    ```
+    */
+    // test: pages_follow_offset
+    pub fn pages(&self, req: &RequestSpec, lookup: Lookup) -> Pages {
+        Pages::new(self.clone(), req.clone(), lookup)
+    }
+
+    /**
+    Send many requests, answering those the cache can straight away and sending the
+    rest to FRED a few at a time.
+    ```no_run
+    # tokio_test::block_on(async {
+    use fred_api::{FredClient, Lookup};
+
+    let client = FredClient::new(sled::open("cache").unwrap(), None).unwrap();
+    let reqs = ["GNPCA", "UNRATE", "CPIAUCSL"]
+        .iter()
+        .map(|id| client.request(&format!("series/observations?series_id={id}&")))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let bodies = client.batch(reqs, Lookup::FredOnCacheMiss)
+        .with_concurrency(8)
+        .collect()
+        .await;
+    # });
+    ```
+    */
+    // test: batch_answers_every_request
+    pub fn batch(&self, reqs: Vec<RequestSpec>, lookup: Lookup) -> Batch {
+        Batch::new(self.clone(), reqs, lookup)
+    }
+
+    /**
+    Send a request to FRED or the cache, using the lookup method to determine procedure.
+    */
+    // test: client_send_cache_only
+    pub async fn send(&self, req: &RequestSpec, lookup: Lookup) -> Result<IVec> {
+        match lookup {
+            Lookup::FredOnCacheMiss => {
+                match cache_request(req, &*self.store) {
+                    Ok(None) => self.fred_request(req).await,
+                    Ok(Some(bytes)) => Ok(bytes),
+                    Err(e) => Err(e),
+                }
+            },
+            Lookup::CacheOnly => {
+                match cache_request(req, &*self.store) {
+                    Ok(Some(bytes)) => Ok(bytes),
+                    Ok(None) => Err(Error::CacheMiss {
+                        mid_part: req.mid_part(),
+                        src: src!("Cache only request (mid-part '{}') failed", req.mid_part()),
+                    })?,
+                    Err(e) => Err(e),
+                }
+            },
+            Lookup::FredOnly => self.fred_request(req).await,
+            Lookup::FredIfOlderThan(age) => self.fred_if_older_than(req, age).await,
+            Lookup::FredIfStale => self.fred_if_older_than(req, self.ttl.ttl(req)).await,
+            Lookup::StaleWhileRevalidate => match self.cached(req, lookup)? {
+                Some((body, stale)) => {
+                    if stale {
+                        self.revalidate(req);
+                    }
+                    Ok(body)
+                },
+                None => self.fred_request(req).await,
+            },
+        }
+    }
+
+    /**
+    The body [`send`](Self::send) would return from the cache without going to FRED, and
+    whether it is stale and should be refreshed, which only ``StaleWhileRevalidate``
+    allows.
+    */
+    // test: batch_returns_stale_entries_without_waiting
+    pub(crate) fn cached(
+        &self,
+        req: &RequestSpec,
+        lookup: Lookup) -> Result<Option<(IVec, bool)>>
+    {
+        let age = match lookup {
+            Lookup::FredOnCacheMiss | Lookup::CacheOnly => {
+                return Ok(cache_request(req, &*self.store)?.map(|body| (body, false)));
+            },
+            Lookup::StaleWhileRevalidate => {
+                let ttl = self.ttl.ttl(req);
+                return Ok(lookup_entry(req, &*self.store)?.map(|entry| {
+                    let stale = entry.is_older_than(ttl);
+                    (entry.body, stale)
+                }));
+            },
+            Lookup::FredIfOlderThan(age) => age,
+            Lookup::FredIfStale => self.ttl.ttl(req),
+            Lookup::FredOnly => return Ok(None),
+        };
+        Ok(lookup_entry(req, &*self.store)?
+            .filter(|entry| !entry.is_older_than(age))
+            .map(|entry| (entry.body, false)))
+    }
+
+    /*
+    Refreshes a stale entry from FRED in the background. Failures are left for the next
+    lookup to find.
+    */
+    pub(crate) fn revalidate(&self, req: &RequestSpec) {
+        let client = self.clone();
+        let req = req.clone();
+        tokio::spawn(async move { client.fred_request(&req).await });
+    }
+
+    // test: client_fred_if_older_than_uses_age
+    async fn fred_if_older_than(&self, req: &RequestSpec, age: Duration) -> Result<IVec> {
+        match lookup_entry(req, &*self.store)? {
+            Some(entry) if !entry.is_older_than(age) => Ok(entry.body),
+            _ => self.fred_request(req).await,
+        }
+    }
+
+    /**
+    Request to FRED bypassing cache, joining the same request if it is already under way.
+    */
+    // test: concurrent_misses_share_one_fetch
+    async fn fred_request(&self, req: &RequestSpec) -> Result<IVec> {
+        let key = (self.store.store_id(), req.ivec());
+        self.in_flight.run(key, self.fetch(req)).await
+    }
+
+    /**
+    Request to FRED, retrying transient failures.
+    */
+    // test: fred_request_should_return_err_on_bad_request
+    // test: fred_request_retries_transient_failures
+    async fn fetch(&self, req: &RequestSpec) -> Result<IVec> {
+        let uri = req.uri_with_base(&self.base_uri)?;
+        if !self.allow_http && uri.scheme_str() == Some("http") {
+            Err(Error::Config(src!(
+                "Plain HTTP to '{}' needs FredClient::allow_http",
+                self.base_uri
+            )))?
+        }
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let failure = match self.fred_attempt(req, uri.clone()).await {
+                Ok(ivec) => return Ok(ivec),
+                Err(failure) => failure,
+            };
+            if !failure.transient || attempt >= self.retry.max_attempts {
+                return Err(failure.err.with_attempts(attempt));
+            }
+            sleep(self.retry.delay(attempt, failure.retry_after)).await;
+        }
+    }
+
+    /**
+    One request to FRED, writing a successful response to the cache.
+    */
+    async fn fred_attempt(
+        &self,
+        req: &RequestSpec,
+        uri: Uri) -> std::result::Result<IVec, Failure>
+    {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire().await;
+        }
+        let res = self.http
+            .get(uri)
+            .await
+            .map_err(|err| Failure::transient(Error::Transport {
+                attempts: 1,
+                src: src!("Request failed with error: {err}."),
+            }))?;
+
+        let status = res.status();
+        let retry_after = retry_after(res.headers());
+        let header = |headers: &HeaderMap, name| {
+            headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string())
+        };
+        let last_modified = header(res.headers(), LAST_MODIFIED);
+        let etag = header(res.headers(), ETAG);
+
+        let body = res
+            .into_body()
+            .collect()
+            .await
+            .map_err(|err| Failure::transient(Error::Transport {
+                attempts: 1,
+                src: src!("Could not get body: {err}."),
+            }))?
+            .to_bytes();
+
+        if status == StatusCode::OK {
+            let meta = EntryMeta {
+                last_modified,
+                etag,
+                format: Format::detect(&body),
+                ..EntryMeta::new(status.as_u16(), body.len() as u64)
+            };
+            write_to_cache(req, body.as_ref(), &meta, self.keep_previous, &*self.store)
+                .map_err(Failure::permanent)
+        } else {
+            let (code, message) = error_details(&body)
+                .unwrap_or((None, "Unknown error".to_string()));
+            let src = src!("FRED API error: '{message}'");
+            let err = if status == StatusCode::TOO_MANY_REQUESTS {
+                Error::RateLimited { retry_after, attempts: 1, src }
+            } else {
+                Error::FredApi { status: status.as_u16(), code, message, attempts: 1, src }
+            };
+            if RetryPolicy::is_transient(status) {
+                Err(Failure { err, transient: true, retry_after })
+            } else {
+                Err(Failure::permanent(err))
+            }
+        }
+    }
+}
+
+// A failed attempt and whether it is worth repeating.
+struct Failure {
+    err: Error,
+    transient: bool,
+    retry_after: Option<Duration>,
+}
+
+impl Failure {
+    fn transient(err: Error) -> Self {
+        Failure { err, transient: true, retry_after: None }
+    }
+
+    fn permanent(err: Error) -> Self {
+        Failure { err, transient: false, retry_after: None }
+    }
+}
+
+impl fmt::Debug for FredClient {
+    // test: client_hides_api_key
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FredClient")
+            .field("key", &format!("({} characters)", self.api_key.len()))
+            .field("lookup", &self.lookup)
+            .field("base_uri", &self.base_uri)
+            .field("allow_http", &self.allow_http)
+            .field("limiter", &self.limiter)
+            .field("retry", &self.retry)
+            .field("ttl", &self.ttl)
+            .field("keep_previous", &self.keep_previous)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::*,
+        crate::testing::{error_body, MockServer},
+        std::time::Duration,
+        tempfile::TempDir,
+    };
+
+    fn create_temp_client(lookup: Lookup) -> (TempDir, FredClient) {
+        let temp_dir = TempDir::new().expect("Failed to create temp directory");
+        let db = sled::open(temp_dir.path()).expect("Failed to open sled database");
+        let client = FredClient::new(db, Some("abcd")).unwrap().with_lookup(lookup);
+        (temp_dir, client)
+    }
+
+    #[tokio::test]
+    async fn client_get_uses_api_key_and_default_lookup() {
+        let (_dir, client) = create_temp_client(Lookup::CacheOnly);
+        let req = client.request("series?series_id=GNPCA&").unwrap();
+        assert!(req.uri().unwrap().to_string().ends_with("api_key=abcd"));
+
+        let entry = CacheEntry { body: "cached".into(), meta: None };
+        client.store().put_entry(&req.ivec(), &entry, false).unwrap();
+        assert_eq!(client.get("series?series_id=GNPCA&").await.unwrap(), "cached");
+    }
+
+    #[tokio::test]
+    async fn client_send_cache_only() {
+        let (_dir, client) = create_temp_client(Lookup::FredOnCacheMiss);
+        let req = client.request("series?series_id=GNPCA&").unwrap();
+        let e = client.send(&req, Lookup::CacheOnly).await.unwrap_err();
+        assert!(matches!(e, Error::CacheMiss { .. }));
+        assert!(e.msg().contains("Cache only request"));
+
+        // Clones share the cache.
+        let entry = CacheEntry { body: "cached".into(), meta: None };
+        client.clone().store().put_entry(&req.ivec(), &entry, false).unwrap();
+        assert_eq!(client.send(&req, Lookup::CacheOnly).await.unwrap(), "cached");
+    }
+
+    async fn create_mock_client(lookup: Lookup) -> (TempDir, MockServer, FredClient) {
+        let server = MockServer::start().await.unwrap();
+        let (dir, client) = create_temp_client(lookup);
+        let client = client
+            .with_base_uri(&server.base_uri()).unwrap()
+            .allow_http(true).unwrap()
+            .with_rate_limit(None)
+            .with_retry(RetryPolicy::none());
+        (dir, server, client)
+    }
+
+    #[tokio::test]
+    async fn client_fetches_from_local_server() {
+        let (_dir, server, client) = create_mock_client(Lookup::FredOnCacheMiss).await;
+        server.insert("series?series_id=GNPCA&", 200, "<series id=\"GNPCA\"/>");
+
+        // The second call is served from the cache.
+        for _ in 0..2 {
+            let bytes = client.get("series?series_id=GNPCA&").await.unwrap();
+            assert_eq!(bytes, "<series id=\"GNPCA\"/>");
+        }
+        assert_eq!(server.hits(), 1);
+
+        // FredOnly always goes to FRED.
+        let req = client.request("series?series_id=GNPCA&").unwrap();
+        client.send(&req, Lookup::FredOnly).await.unwrap();
+        assert_eq!(server.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn fred_request_records_metadata() {
+        let (_dir, server, client) = create_mock_client(Lookup::FredOnCacheMiss).await;
+        server.insert("series?", 200, "<series/>");
+        let req = client.request("series?series_id=GNPCA&").unwrap();
+        client.send(&req, Lookup::FredOnCacheMiss).await.unwrap();
+
+        let entry = lookup_entry(&req, client.store()).unwrap().unwrap();
+        let meta = entry.meta.unwrap();
+        assert_eq!(entry.body, "<series/>");
+        assert_eq!((meta.status, meta.length, meta.etag), (200, 9, None));
+    }
+
+    #[tokio::test]
+    async fn fred_only_replaces_cached_value() {
+        let (_dir, server, client) = create_mock_client(Lookup::FredOnly).await;
+        let req = client.request("series?series_id=GNPCA&").unwrap();
+        server.insert("series?", 200, "v1");
+        assert_eq!(client.send(&req, Lookup::FredOnly).await.unwrap(), "v1");
+
+        server.insert("series?", 200, "v2");
+        assert_eq!(client.send(&req, Lookup::FredOnly).await.unwrap(), "v2");
+        assert_eq!(cache_request(&req, client.store()).unwrap().unwrap(), "v2");
+        assert!(previous_entry(&req, client.store()).unwrap().is_none());
+
+        // Refreshes keep the replaced value when asked.
+        let client = client.with_previous_versions(true);
+        server.insert("series?", 200, "v3");
+        let hour = Duration::from_secs(3600);
+        assert_eq!(client.send(&req, Lookup::FredIfOlderThan(hour)).await.unwrap(), "v2");
+        assert_eq!(client.send(&req, Lookup::FredIfOlderThan(Duration::ZERO)).await.unwrap(), "v3");
+        assert_eq!(previous_entry(&req, client.store()).unwrap().unwrap().body, "v2");
+        assert_eq!(lookup_entry(&req, client.store()).unwrap().unwrap().meta.unwrap().length, 2);
+    }
+
+    #[tokio::test]
+    async fn client_fred_if_older_than_uses_age() {
+        let (_dir, server, client) = create_mock_client(Lookup::FredOnCacheMiss).await;
+        server.insert("series?", 200, "<series/>");
+        let req = client.request("series?series_id=GNPCA&").unwrap();
+        let hour = Duration::from_secs(3600);
+
+        client.send(&req, Lookup::FredIfOlderThan(hour)).await.unwrap();
+        client.send(&req, Lookup::FredIfOlderThan(hour)).await.unwrap();
+        assert_eq!(server.hits(), 1);
+        client.send(&req, Lookup::FredIfOlderThan(Duration::ZERO)).await.unwrap();
+        assert_eq!(server.hits(), 2);
+
+        let client = client.with_ttl(TtlPolicy::uniform(hour));
+        client.send(&req, Lookup::FredIfStale).await.unwrap();
+        assert_eq!(server.hits(), 2);
+        let client = client.with_ttl(TtlPolicy::default().with_ttl("series", Duration::ZERO));
+        client.send(&req, Lookup::FredIfStale).await.unwrap();
+        assert_eq!(server.hits(), 3);
+    }
+
+    #[tokio::test]
+    async fn client_stale_while_revalidate_refreshes_in_background() {
+        let (_dir, server, client) = create_mock_client(Lookup::StaleWhileRevalidate).await;
+        server.insert("series?", 200, "<series/>");
+
+        // A miss is fetched.
+        assert_eq!(client.get("series?series_id=GNPCA&").await.unwrap(), "<series/>");
+        assert_eq!(server.hits(), 1);
+
+        // A fresh entry is not refreshed.
+        client.get("series?series_id=GNPCA&").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(server.hits(), 1);
+
+        // An entry without metadata is stale, so it is returned and then refreshed.
+        let req = client.request("series?series_id=UNRATE&").unwrap();
+        let entry = CacheEntry { body: "stale".into(), meta: None };
+        client.store().put_entry(&req.ivec(), &entry, false).unwrap();
+        assert_eq!(client.get("series?series_id=UNRATE&").await.unwrap(), "stale");
+        for _ in 0..100 {
+            if server.hits() == 2 { break }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(server.requests()[1], "series?series_id=UNRATE&");
+    }
+
+    #[tokio::test]
+    async fn fred_request_should_return_err_on_bad_request() {
+        let (_dir, server, client) = create_mock_client(Lookup::FredOnCacheMiss).await;
+        let message = "Bad Request. The series does not exist.";
+        server.insert_error("series?series_id=BAD&", 400, message);
+        server.insert("tags?", 404, "Not XML");
+
+        let e = client.get("series?series_id=BAD&").await.unwrap_err();
+        assert_eq!(e.msg(), "FRED API error: 'Bad Request. The series does not exist.'");
+        match e {
+            Error::FredApi { status, code, message, attempts, .. } => {
+                assert_eq!((status, code, attempts), (400, Some(400), 1));
+                assert_eq!(message, "Bad Request. The series does not exist.");
+            },
+            e => panic!("{e:?}"),
+        }
+        let e = client.get("tags?").await.unwrap_err();
+        assert!(matches!(e, Error::FredApi { status: 404, code: None, .. }));
+        assert_eq!(e.msg(), "FRED API error: 'Unknown error'");
+
+        // Errors are not cached.
+        let req = client.request("series?series_id=BAD&").unwrap();
+        assert!(cache_request(&req, client.store()).unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn fred_request_reads_json_responses() {
+        let (_dir, server, client) = create_mock_client(Lookup::FredOnCacheMiss).await;
+        let body = r#"{"error_code":400,"error_message":"Bad Request.  The series does not exist."}"#;
+        server.insert("series?file_type=json&series_id=BAD&", 400, body);
+        server.insert("series?file_type=json&", 200, r#"{"seriess":[]}"#);
+
+        let e = client.get("series?series_id=BAD&file_type=json&").await.unwrap_err();
+        match e {
+            Error::FredApi { status: 400, code: Some(400), message, .. } => {
+                assert_eq!(message, "Bad Request.  The series does not exist.");
+            },
+            e => panic!("{e:?}"),
+        }
+
+        let req = client.request("series?series_id=GNPCA&file_type=json&").unwrap();
+        assert_eq!(req.format(), Format::Json);
+        client.send(&req, Lookup::FredOnly).await.unwrap();
+        let meta = lookup_entry(&req, client.store()).unwrap().unwrap().meta.unwrap();
+        assert_eq!(meta.format, Format::Json);
+    }
+
+    #[tokio::test]
+    async fn fred_request_retries_transient_failures() {
+        let (_dir, server, client) = create_mock_client(Lookup::FredOnly).await;
+        let client = client.with_retry(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        });
+
+        // Recovers from two transient failures.
+        server.insert("series?", 200, "<series/>");
+        server.queue("series?", 503, error_body(503, "Service Unavailable"));
+        server.queue("series?", 429, error_body(429, "Too Many Requests"));
+        assert_eq!(client.get("series?series_id=GNPCA&").await.unwrap(), "<series/>");
+        assert_eq!(server.hits(), 3);
+
+        // Gives up after the last attempt.
+        server.insert_error("tags?", 500, "Internal Server Error");
+        let e = client.get("tags?").await.unwrap_err();
+        assert_eq!(e.attempts(), Some(3));
+        assert!(e.to_string().ends_with("'Internal Server Error' Gave up after 3 attempts."));
+        assert_eq!(server.hits(), 6);
+
+        // Repeated 429s are reported as rate limiting.
+        server.insert_error("sources?", 429, "Too Many Requests");
+        let e = client.get("sources?").await.unwrap_err();
+        assert!(matches!(e, Error::RateLimited { attempts: 3, .. }));
+        assert_eq!(server.hits(), 9);
+
+        // A bad request is never retried.
+        server.insert_error("series?series_id=BAD&", 400, "Bad Request.");
+        let e = client.get("series?series_id=BAD&").await.unwrap_err();
+        assert!(matches!(e, Error::FredApi { status: 400, attempts: 1, .. }));
+        assert_eq!(server.hits(), 10);
+
+        // A permanent failure after a transient one still counts every attempt.
+        server.insert_error("releases?", 400, "Bad Request.");
+        server.queue("releases?", 503, error_body(503, "Service Unavailable"));
+        let e = client.get("releases?").await.unwrap_err();
+        assert!(matches!(e, Error::FredApi { status: 400, attempts: 2, .. }));
+        assert!(e.to_string().ends_with("Gave up after 2 attempts."));
+        assert_eq!(server.hits(), 12);
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses_share_one_fetch() {
+        let (_dir, server, client) = create_mock_client(Lookup::FredOnCacheMiss).await;
+        server.insert("series?series_id=GNPCA&", 200, "<series id=\"GNPCA\"/>");
+        let clone = client.clone();
+        let (a, b, c) = tokio::join!(
+            client.get("series?series_id=GNPCA&"),
+            clone.get("series?series_id=GNPCA&"),
+            client.get("series?series_id=UNRATE&"),
+        );
+        assert_eq!(a.unwrap(), b.unwrap());
+        assert!(c.is_err());
+        assert_eq!(server.hits(), 2);
+
+        // Errors are shared too, and the next request fetches again.
+        server.insert_error("series?series_id=BAD&", 400, "Bad Request.");
+        let req = client.request("series?series_id=BAD&").unwrap();
+        let (a, b) = tokio::join!(
+            client.send(&req, Lookup::FredOnly),
+            client.send(&req, Lookup::FredOnly),
+        );
+        assert!(matches!((a, b), (Err(Error::FredApi { .. }), Err(Error::FredApi { .. }))));
+        assert_eq!(server.hits(), 3);
+        client.send(&req, Lookup::FredOnly).await.unwrap_err();
+        assert_eq!(server.hits(), 4);
+    }
+
+    // A client made as `send_request` makes one, joining the process-wide fetches under way.
+    fn send_request_client(server: &MockServer, store: &(impl CacheStore + Clone + 'static))
+        -> FredClient
+    {
+        FredClient::shared(std::sync::Arc::new(store.clone())).unwrap()
+            .with_base_uri(&server.base_uri()).unwrap()
+            .allow_http(true).unwrap()
+            .with_rate_limit(None)
+    }
+
+    #[tokio::test]
+    async fn shared_fetches_are_per_store() {
+        let server = MockServer::start().await.unwrap();
+        server.insert("series?series_id=GNPCA&", 200, "<series id=\"GNPCA\"/>");
+        let (dir_a, dir_b) = (TempDir::new().unwrap(), TempDir::new().unwrap());
+        let (db_a, db_b) = (sled::open(dir_a.path()).unwrap(), sled::open(dir_b.path()).unwrap());
+        let client = |db: &sled::Db| send_request_client(&server, db);
+        let req = build_request("series?series_id=GNPCA&", Some("abcd")).unwrap();
+        let (a, b, a2) = (client(&db_a), client(&db_b), client(&db_a));
+        let (a, b, a2) = tokio::join!(
+            a.send(&req, Lookup::FredOnCacheMiss),
+            b.send(&req, Lookup::FredOnCacheMiss),
+            a2.send(&req, Lookup::FredOnCacheMiss),
+        );
+        assert_eq!((a.unwrap(), b.unwrap()), (a2.unwrap(), "<series id=\"GNPCA\"/>".into()));
+        // One fetch for each store, and each store has the response.
+        assert_eq!(server.hits(), 2);
+        assert!(cache_request(&req, &db_a).unwrap().is_some());
+        assert!(cache_request(&req, &db_b).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn shared_fetches_join_on_fs_store_clones() {
+        let server = MockServer::start().await.unwrap();
+        server.insert("series?series_id=UNRATE&", 200, "<series id=\"UNRATE\"/>");
+        let dir = TempDir::new().unwrap();
+        let store = FsStore::new(dir.path()).unwrap();
+        let other = FsStore::new(dir.path().join("other")).unwrap();
+        let req = build_request("series?series_id=UNRATE&", Some("abcd")).unwrap();
+        let clients = [&store, &store, &other].map(|store| send_request_client(&server, store));
+        let (a, b, c) = tokio::join!(
+            clients[0].send(&req, Lookup::FredOnCacheMiss),
+            clients[1].send(&req, Lookup::FredOnCacheMiss),
+            clients[2].send(&req, Lookup::FredOnCacheMiss),
+        );
+        assert_eq!((a.unwrap(), b.unwrap()), (c.unwrap(), "<series id=\"UNRATE\"/>".into()));
+        // Clones of one store share a fetch, another store fetches for itself.
+        assert_eq!(server.hits(), 2);
+        assert!(cache_request(&req, &other).unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn client_rate_limits_only_cache_misses() {
+        let (_dir, server, client) = create_mock_client(Lookup::FredOnCacheMiss).await;
+        let client = client.with_rate_limit(Some(RateLimiter::new(1, 1)));
+        server.insert("series?", 200, "<series/>");
+
+        client.get("series?series_id=GNPCA&").await.unwrap();
+        for _ in 0..100 {
+            client.get("series?series_id=GNPCA&").await.unwrap();
+        }
+
+        // A miss on a clone queues behind the shared limiter instead of failing.
+        let clone = client.clone();
+        let miss = clone.get("series?series_id=UNRATE&");
+        let waited = tokio::time::timeout(Duration::from_millis(200), miss).await;
+        assert!(waited.is_err());
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn client_refuses_http_by_default() {
+        let server = MockServer::start().await.unwrap();
+        server.insert("series?", 200, "<series/>");
+        let (_dir, client) = create_temp_client(Lookup::FredOnly);
+        let client = client.with_base_uri(&server.base_uri()).unwrap();
+        let e = client.get("series?series_id=GNPCA&").await.unwrap_err();
+        assert!(matches!(e, Error::Config(_)));
+        assert!(e.msg().contains("needs FredClient::allow_http"));
+        assert_eq!(server.hits(), 0);
+
+        let (_dir, client) = create_temp_client(Lookup::FredOnly);
+        assert!(client.with_base_uri("ftp://127.0.0.1/fred").is_err());
+    }
+
+    #[test]
+    fn client_hides_api_key() {
+        let (_dir, client) = create_temp_client(Lookup::CacheOnly);
+        let debug = format!("{client:?}");
+        assert!(debug.starts_with("FredClient { key: \"(4 characters)\", lookup: CacheOnly, \
+            base_uri: \"https://api.stlouisfed.org/fred\", allow_http: false, \
+            limiter: Some(RateLimiter { per_minute: 120.0, burst: 1.0 }), \
+            retry: RetryPolicy { max_attempts: 4,"));
+        assert!(!debug.contains("abcd"));
+    }
+}
@@ -0,0 +1,733 @@
+/*!
+Typed builders for the FRED endpoints, grouped by endpoint family.
+
+Each builder starts from a family ([`Category`], [`Release`], [`Series`], [`Sources`],
+[`Tags`]) and only has methods for the parameters that FRED documents for that
+endpoint, so a misspelt path or parameter fails to compile rather than failing at FRED.
+```
+use fred_api::endpoints::{Series, Units, Frequency, AggregationMethod};
+
+let req = Series::observations("GNPCA")
+    .observation_start("2000-01-01")
+    .units(Units::Pch)
+    .frequency(Frequency::Annual)
+    .aggregation_method(AggregationMethod::Avg)
+    .build(Some("abcd"))
+    .unwrap();
+assert_eq!(
+    req.mid_part(),
+    "series/observations?series_id=GNPCA&observation_start=2000-01-01&units=pch&frequency=a&aggregation_method=avg&",
+);
+```
+*/
+
+use {
+    crate::{src, DebugErr, Error, RequestSpec, Result},
+    std::{fmt::{self, Write}, str::FromStr},
+};
+
+/**
+A value that can be written as a FRED query parameter.
+*/
+pub trait ParamValue {
+    fn param_value(&self) -> String;
+}
+
+impl ParamValue for &str {
+    fn param_value(&self) -> String { self.to_string() }
+}
+
+impl ParamValue for String {
+    fn param_value(&self) -> String { self.clone() }
+}
+
+impl ParamValue for u32 {
+    fn param_value(&self) -> String { self.to_string() }
+}
+
+impl ParamValue for bool {
+    fn param_value(&self) -> String { self.to_string() }
+}
+
+/// Lists such as ``tag_names`` are semicolon delimited.
+impl ParamValue for &[&str] {
+    fn param_value(&self) -> String { self.join(";") }
+}
+
+impl<const N: usize> ParamValue for [&str; N] {
+    fn param_value(&self) -> String { self.join(";") }
+}
+
+/**
+Percent-encode a parameter value, leaving the characters FRED uses as list delimiters.
+*/
+// test: encode_value_works
+pub(crate) fn encode_value(value: &str) -> String {
+    let mut s = String::with_capacity(value.len());
+    for b in value.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9'
+            | b'-' | b'_' | b'.' | b'~' | b';' | b',' | b':' => s.push(b as char),
+            _ => { let _ = write!(s, "%{b:02X}"); },
+        }
+    }
+    s
+}
+
+/**
+Decode a percent-encoded parameter value, reading ``+`` as a space as FRED's examples do.
+Malformed escapes are kept as they are.
+*/
+// test: decode_value_works
+pub(crate) fn decode_value(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match hex {
+                    Some(b) => { out.push(b); i += 2; },
+                    None => out.push(b'%'),
+                }
+            },
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// Enumerated parameter values, written as FRED spells them.
+macro_rules! value_enum {
+    ($(#[$doc:meta])* $name:ident { $($variant:ident => $value:literal),* $(,)? }) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+        pub enum $name {
+            $($variant),*
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $value),*
+                }
+            }
+        }
+
+        impl ParamValue for $name {
+            fn param_value(&self) -> String { self.as_str().to_string() }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.as_str()) }
+        }
+
+        impl FromStr for $name {
+            type Err = Error;
+
+            fn from_str(s: &str) -> Result<Self> {
+                match s {
+                    $($value => Ok($name::$variant),)*
+                    _ => Err(Error::Config(src!(
+                        "Could not parse '{}' as {}",
+                        s,
+                        stringify!($name)
+                    ))),
+                }
+            }
+        }
+
+        serde_via_str!($name);
+    };
+}
+
+value_enum!(
+    /// Data value transformation for ``series/observations``.
+    Units {
+        Lin => "lin",
+        Chg => "chg",
+        Ch1 => "ch1",
+        Pch => "pch",
+        Pc1 => "pc1",
+        Pca => "pca",
+        Cch => "cch",
+        Cca => "cca",
+        Log => "log",
+    }
+);
+
+value_enum!(
+    /// Frequency to aggregate ``series/observations`` to.
+    Frequency {
+        Daily => "d",
+        Weekly => "w",
+        Biweekly => "bw",
+        Monthly => "m",
+        Quarterly => "q",
+        Semiannual => "sa",
+        Annual => "a",
+        WeeklyEndingFriday => "wef",
+        WeeklyEndingThursday => "weth",
+        WeeklyEndingWednesday => "wew",
+        WeeklyEndingTuesday => "wetu",
+        WeeklyEndingMonday => "wem",
+        WeeklyEndingSunday => "wesu",
+        WeeklyEndingSaturday => "wesa",
+        BiweeklyEndingWednesday => "bwew",
+        BiweeklyEndingMonday => "bwem",
+    }
+);
+
+value_enum!(
+    /// Aggregation method used with a frequency aggregation.
+    AggregationMethod {
+        Avg => "avg",
+        Sum => "sum",
+        Eop => "eop",
+    }
+);
+
+value_enum!(
+    SortOrder {
+        Asc => "asc",
+        Desc => "desc",
+    }
+);
+
+value_enum!(
+    /// Output type for ``series/observations``.
+    OutputType {
+        RealtimePeriod => "1",
+        VintageAll => "2",
+        VintageNew => "3",
+        InitialRelease => "4",
+    }
+);
+
+value_enum!(
+    SearchType {
+        FullText => "full_text",
+        SeriesId => "series_id",
+    }
+);
+
+value_enum!(
+    /// Series attribute used with ``filter_value``.
+    FilterVariable {
+        Frequency => "frequency",
+        Units => "units",
+        SeasonalAdjustment => "seasonal_adjustment",
+    }
+);
+
+value_enum!(
+    /// The series ``series/updates`` lists with ``filter_value``.
+    UpdatesFilter {
+        Macro => "macro",
+        Regional => "regional",
+        All => "all",
+    }
+);
+
+value_enum!(
+    /// Response format. XML is FRED's default, JSON needs the ``json`` feature to parse.
+    FileType {
+        Xml => "xml",
+        Json => "json",
+    }
+);
+
+value_enum!(
+    TagGroupId {
+        Frequency => "freq",
+        General => "gen",
+        Geography => "geo",
+        GeographyType => "geot",
+        Release => "rls",
+        SeasonalAdjustment => "seas",
+        Source => "src",
+        CitationCopyright => "cc",
+    }
+);
+
+value_enum!(
+    /// Ordering attribute. FRED accepts a different subset for each endpoint.
+    OrderBy {
+        SeriesId => "series_id",
+        Title => "title",
+        Units => "units",
+        Frequency => "frequency",
+        SeasonalAdjustment => "seasonal_adjustment",
+        RealtimeStart => "realtime_start",
+        RealtimeEnd => "realtime_end",
+        LastUpdated => "last_updated",
+        ObservationStart => "observation_start",
+        ObservationEnd => "observation_end",
+        ObservationDate => "observation_date",
+        Popularity => "popularity",
+        GroupPopularity => "group_popularity",
+        SearchRank => "search_rank",
+        ReleaseId => "release_id",
+        ReleaseName => "release_name",
+        ReleaseDate => "release_date",
+        SourceId => "source_id",
+        Name => "name",
+        PressRelease => "press_release",
+        SeriesCount => "series_count",
+        Created => "created",
+        GroupId => "group_id",
+    }
+);
+
+// Optional parameter methods, one signature per FRED parameter.
+macro_rules! param {
+    (realtime) => {
+        pub fn realtime_start(self, date: &str) -> Self { self.param("realtime_start", date) }
+        pub fn realtime_end(self, date: &str) -> Self { self.param("realtime_end", date) }
+    };
+    (paging) => {
+        pub fn limit(self, limit: u32) -> Self { self.param("limit", limit) }
+        pub fn offset(self, offset: u32) -> Self { self.param("offset", offset) }
+    };
+    (sort_order) => {
+        pub fn sort_order(self, sort_order: SortOrder) -> Self {
+            self.param("sort_order", sort_order)
+        }
+    };
+    (order_by) => {
+        pub fn order_by(self, order_by: OrderBy) -> Self { self.param("order_by", order_by) }
+    };
+    (filter) => {
+        pub fn filter_variable(self, variable: FilterVariable) -> Self {
+            self.param("filter_variable", variable)
+        }
+        pub fn filter_value(self, value: &str) -> Self { self.param("filter_value", value) }
+    };
+    (tag_names) => {
+        pub fn tag_names(self, tag_names: &[&str]) -> Self { self.param("tag_names", tag_names) }
+    };
+    (exclude_tag_names) => {
+        pub fn exclude_tag_names(self, tag_names: &[&str]) -> Self {
+            self.param("exclude_tag_names", tag_names)
+        }
+    };
+    (tag_group_id) => {
+        pub fn tag_group_id(self, group: TagGroupId) -> Self { self.param("tag_group_id", group) }
+    };
+    (search_text) => {
+        pub fn search_text(self, text: &str) -> Self { self.param("search_text", text) }
+    };
+    (tag_search_text) => {
+        pub fn tag_search_text(self, text: &str) -> Self { self.param("tag_search_text", text) }
+    };
+    (search_type) => {
+        pub fn search_type(self, search_type: SearchType) -> Self {
+            self.param("search_type", search_type)
+        }
+    };
+    (observations) => {
+        pub fn observation_start(self, date: &str) -> Self {
+            self.param("observation_start", date)
+        }
+        pub fn observation_end(self, date: &str) -> Self { self.param("observation_end", date) }
+        pub fn units(self, units: Units) -> Self { self.param("units", units) }
+        pub fn frequency(self, frequency: Frequency) -> Self { self.param("frequency", frequency) }
+        pub fn aggregation_method(self, method: AggregationMethod) -> Self {
+            self.param("aggregation_method", method)
+        }
+        pub fn output_type(self, output_type: OutputType) -> Self {
+            self.param("output_type", output_type)
+        }
+        pub fn vintage_dates(self, dates: &[&str]) -> Self {
+            self.param("vintage_dates", dates.join(","))
+        }
+    };
+    (include_release_dates_with_no_data) => {
+        pub fn include_release_dates_with_no_data(self, include: bool) -> Self {
+            self.param("include_release_dates_with_no_data", include)
+        }
+    };
+    (tables) => {
+        pub fn element_id(self, element_id: u32) -> Self { self.param("element_id", element_id) }
+        pub fn include_observation_values(self, include: bool) -> Self {
+            self.param("include_observation_values", include)
+        }
+        pub fn observation_date(self, date: &str) -> Self {
+            self.param("observation_date", date)
+        }
+    };
+    (updates) => {
+        pub fn start_time(self, time: &str) -> Self { self.param("start_time", time) }
+        pub fn end_time(self, time: &str) -> Self { self.param("end_time", time) }
+        pub fn filter_value(self, filter: UpdatesFilter) -> Self {
+            self.param("filter_value", filter)
+        }
+    };
+}
+
+// A builder for one endpoint. Required parameters are constructor arguments.
+macro_rules! endpoint {
+    (
+        $(#[$doc:meta])*
+        $name:ident, $path:literal, ($($req:ident: $ty:ty),*), [$($opt:ident),*]
+    ) => {
+        $(#[$doc])*
+        #[derive(Clone, Debug)]
+        pub struct $name {
+            params: Vec<(&'static str, String)>,
+        }
+
+        impl $name {
+            pub const PATH: &'static str = $path;
+
+            #[allow(clippy::new_without_default)]
+            pub fn new($($req: $ty),*) -> Self {
+                let s = $name { params: Vec::new() };
+                $(let s = s.param(stringify!($req), $req);)*
+                s
+            }
+
+            $(param!($opt);)*
+
+            pub fn file_type(self, file_type: FileType) -> Self {
+                self.param("file_type", file_type)
+            }
+
+            // Replaces any earlier value for the same parameter.
+            fn param(mut self, name: &'static str, value: impl ParamValue) -> Self {
+                self.params.retain(|(n, _)| *n != name);
+                self.params.push((name, value.param_value()));
+                self
+            }
+
+            /**
+            The mid-part of the request, ending with the ``&`` or ``?`` that precedes
+            ``api_key``.
+            */
+            pub fn mid_part(&self) -> String {
+                let mut s = format!("{}?", Self::PATH);
+                for (name, value) in &self.params {
+                    let _ = write!(s, "{}={}&", name, encode_value(value));
+                }
+                s
+            }
+
+            /**
+            If ``api_key`` is ``None``, checks for environment variable ``FRED_API_KEY``.
+            */
+            pub fn build(&self, api_key: Option<&str>) -> Result<RequestSpec> {
+                RequestSpec::new(&self.mid_part(), api_key)
+            }
+        }
+    };
+}
+
+endpoint!(CategoryGet, "category", (category_id: u32), []);
+endpoint!(CategoryChildren, "category/children", (category_id: u32), [realtime]);
+endpoint!(CategoryRelated, "category/related", (category_id: u32), [realtime]);
+endpoint!(
+    CategorySeries,
+    "category/series",
+    (category_id: u32),
+    [realtime, paging, order_by, sort_order, filter, tag_names, exclude_tag_names]
+);
+endpoint!(
+    CategoryTags,
+    "category/tags",
+    (category_id: u32),
+    [realtime, tag_names, tag_group_id, search_text, paging, order_by, sort_order]
+);
+endpoint!(
+    CategoryRelatedTags,
+    "category/related_tags",
+    (category_id: u32, tag_names: &[&str]),
+    [realtime, exclude_tag_names, tag_group_id, search_text, paging, order_by, sort_order]
+);
+
+endpoint!(ReleasesAll, "releases", (), [realtime, paging, order_by, sort_order]);
+endpoint!(
+    ReleasesDates,
+    "releases/dates",
+    (),
+    [realtime, paging, order_by, sort_order, include_release_dates_with_no_data]
+);
+endpoint!(ReleaseGet, "release", (release_id: u32), [realtime]);
+endpoint!(
+    ReleaseDates,
+    "release/dates",
+    (release_id: u32),
+    [realtime, paging, sort_order, include_release_dates_with_no_data]
+);
+endpoint!(
+    ReleaseSeries,
+    "release/series",
+    (release_id: u32),
+    [realtime, paging, order_by, sort_order, filter, tag_names, exclude_tag_names]
+);
+endpoint!(ReleaseSources, "release/sources", (release_id: u32), [realtime]);
+endpoint!(
+    ReleaseTags,
+    "release/tags",
+    (release_id: u32),
+    [realtime, tag_names, tag_group_id, search_text, paging, order_by, sort_order]
+);
+endpoint!(
+    ReleaseRelatedTags,
+    "release/related_tags",
+    (release_id: u32, tag_names: &[&str]),
+    [realtime, exclude_tag_names, tag_group_id, search_text, paging, order_by, sort_order]
+);
+endpoint!(ReleaseTables, "release/tables", (release_id: u32), [tables]);
+
+endpoint!(SeriesGet, "series", (series_id: &str), [realtime]);
+endpoint!(SeriesCategories, "series/categories", (series_id: &str), [realtime]);
+endpoint!(
+    SeriesObservations,
+    "series/observations",
+    (series_id: &str),
+    [realtime, paging, sort_order, observations]
+);
+endpoint!(SeriesRelease, "series/release", (series_id: &str), [realtime]);
+endpoint!(
+    SeriesSearch,
+    "series/search",
+    (search_text: &str),
+    [search_type, realtime, paging, order_by, sort_order, filter, tag_names, exclude_tag_names]
+);
+endpoint!(
+    SeriesSearchTags,
+    "series/search/tags",
+    (series_search_text: &str),
+    [realtime, tag_names, tag_group_id, tag_search_text, paging, order_by, sort_order]
+);
+endpoint!(
+    SeriesSearchRelatedTags,
+    "series/search/related_tags",
+    (series_search_text: &str, tag_names: &[&str]),
+    [realtime, exclude_tag_names, tag_group_id, tag_search_text, paging, order_by, sort_order]
+);
+endpoint!(SeriesTags, "series/tags", (series_id: &str), [realtime, order_by, sort_order]);
+endpoint!(SeriesUpdates, "series/updates", (), [realtime, paging, updates]);
+endpoint!(
+    SeriesVintageDates,
+    "series/vintagedates",
+    (series_id: &str),
+    [realtime, paging, sort_order]
+);
+
+endpoint!(SourcesAll, "sources", (), [realtime, paging, order_by, sort_order]);
+endpoint!(SourceGet, "source", (source_id: u32), [realtime]);
+endpoint!(
+    SourceReleases,
+    "source/releases",
+    (source_id: u32),
+    [realtime, paging, order_by, sort_order]
+);
+
+endpoint!(
+    TagsAll,
+    "tags",
+    (),
+    [realtime, tag_names, tag_group_id, search_text, paging, order_by, sort_order]
+);
+endpoint!(
+    TagsRelated,
+    "related_tags",
+    (tag_names: &[&str]),
+    [realtime, exclude_tag_names, tag_group_id, search_text, paging, order_by, sort_order]
+);
+endpoint!(
+    TagsSeries,
+    "tags/series",
+    (tag_names: &[&str]),
+    [exclude_tag_names, realtime, paging, order_by, sort_order]
+);
+
+/**
+``category`` endpoints.
+*/
+pub struct Category;
+
+impl Category {
+    pub fn get(category_id: u32) -> CategoryGet { CategoryGet::new(category_id) }
+
+    pub fn children(category_id: u32) -> CategoryChildren { CategoryChildren::new(category_id) }
+
+    pub fn related(category_id: u32) -> CategoryRelated { CategoryRelated::new(category_id) }
+
+    pub fn series(category_id: u32) -> CategorySeries { CategorySeries::new(category_id) }
+
+    pub fn tags(category_id: u32) -> CategoryTags { CategoryTags::new(category_id) }
+
+    pub fn related_tags(category_id: u32, tag_names: &[&str]) -> CategoryRelatedTags {
+        CategoryRelatedTags::new(category_id, tag_names)
+    }
+}
+
+/**
+``releases`` and ``release`` endpoints.
+*/
+pub struct Release;
+
+impl Release {
+    pub fn all() -> ReleasesAll { ReleasesAll::new() }
+
+    pub fn all_dates() -> ReleasesDates { ReleasesDates::new() }
+
+    pub fn get(release_id: u32) -> ReleaseGet { ReleaseGet::new(release_id) }
+
+    pub fn dates(release_id: u32) -> ReleaseDates { ReleaseDates::new(release_id) }
+
+    pub fn series(release_id: u32) -> ReleaseSeries { ReleaseSeries::new(release_id) }
+
+    pub fn sources(release_id: u32) -> ReleaseSources { ReleaseSources::new(release_id) }
+
+    pub fn tags(release_id: u32) -> ReleaseTags { ReleaseTags::new(release_id) }
+
+    pub fn related_tags(release_id: u32, tag_names: &[&str]) -> ReleaseRelatedTags {
+        ReleaseRelatedTags::new(release_id, tag_names)
+    }
+
+    pub fn tables(release_id: u32) -> ReleaseTables { ReleaseTables::new(release_id) }
+}
+
+/**
+``series`` endpoints.
+*/
+pub struct Series;
+
+impl Series {
+    pub fn get(series_id: &str) -> SeriesGet { SeriesGet::new(series_id) }
+
+    pub fn categories(series_id: &str) -> SeriesCategories { SeriesCategories::new(series_id) }
+
+    pub fn observations(series_id: &str) -> SeriesObservations {
+        SeriesObservations::new(series_id)
+    }
+
+    pub fn release(series_id: &str) -> SeriesRelease { SeriesRelease::new(series_id) }
+
+    pub fn search(search_text: &str) -> SeriesSearch { SeriesSearch::new(search_text) }
+
+    pub fn search_tags(series_search_text: &str) -> SeriesSearchTags {
+        SeriesSearchTags::new(series_search_text)
+    }
+
+    pub fn search_related_tags(
+        series_search_text: &str,
+        tag_names: &[&str]) -> SeriesSearchRelatedTags
+    {
+        SeriesSearchRelatedTags::new(series_search_text, tag_names)
+    }
+
+    pub fn tags(series_id: &str) -> SeriesTags { SeriesTags::new(series_id) }
+
+    pub fn updates() -> SeriesUpdates { SeriesUpdates::new() }
+
+    pub fn vintage_dates(series_id: &str) -> SeriesVintageDates {
+        SeriesVintageDates::new(series_id)
+    }
+}
+
+/**
+``sources`` and ``source`` endpoints.
+*/
+pub struct Sources;
+
+impl Sources {
+    pub fn all() -> SourcesAll { SourcesAll::new() }
+
+    pub fn get(source_id: u32) -> SourceGet { SourceGet::new(source_id) }
+
+    pub fn releases(source_id: u32) -> SourceReleases { SourceReleases::new(source_id) }
+}
+
+/**
+``tags``, ``related_tags`` and ``tags/series`` endpoints.
+*/
+pub struct Tags;
+
+impl Tags {
+    pub fn all() -> TagsAll { TagsAll::new() }
+
+    pub fn related(tag_names: &[&str]) -> TagsRelated { TagsRelated::new(tag_names) }
+
+    pub fn series(tag_names: &[&str]) -> TagsSeries { TagsSeries::new(tag_names) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_value_works() {
+        assert_eq!(encode_value("GNPCA"), "GNPCA");
+        assert_eq!(encode_value("monetary service index"), "monetary%20service%20index");
+        assert_eq!(encode_value("slovenia;food;oecd"), "slovenia;food;oecd");
+        assert_eq!(encode_value("a&b=c"), "a%26b%3Dc");
+    }
+
+    #[test]
+    fn decode_value_works() {
+        assert_eq!(decode_value("monetary+service%20index"), "monetary service index");
+        assert_eq!(decode_value("a%26b%3dc"), "a&b=c");
+        assert_eq!(decode_value("100%"), "100%");
+        assert_eq!(decode_value("%zz"), "%zz");
+        assert_eq!(decode_value(&encode_value("a b&c")), "a b&c");
+    }
+
+    #[test]
+    fn mid_parts_agree_with_fred_documentation() {
+        let samples = vec![
+            (Category::get(125).mid_part(), "category?category_id=125&"),
+            (Category::children(13).mid_part(), "category/children?category_id=13&"),
+            (
+                Category::related_tags(125, &["services", "quarterly"]).mid_part(),
+                "category/related_tags?category_id=125&tag_names=services;quarterly&",
+            ),
+            (Release::all().mid_part(), "releases?"),
+            (Release::series(51).mid_part(), "release/series?release_id=51&"),
+            (Series::observations("GNPCA").mid_part(), "series/observations?series_id=GNPCA&"),
+            (
+                Series::search("monetary service index").mid_part(),
+                "series/search?search_text=monetary%20service%20index&",
+            ),
+            (
+                Series::updates().filter_value(UpdatesFilter::Regional).mid_part(),
+                "series/updates?filter_value=regional&",
+            ),
+            (Sources::releases(1).mid_part(), "source/releases?source_id=1&"),
+            (Tags::series(&["slovenia", "food", "oecd"]).mid_part(),
+                "tags/series?tag_names=slovenia;food;oecd&"),
+        ];
+        for (mid_part, expected) in samples {
+            assert_eq!(mid_part, expected);
+        }
+    }
+
+    #[test]
+    fn later_parameter_replaces_earlier() {
+        let mid_part = Series::observations("GNPCA")
+            .units(Units::Lin)
+            .limit(10)
+            .units(Units::Pc1)
+            .mid_part();
+        assert_eq!(mid_part, "series/observations?series_id=GNPCA&limit=10&units=pc1&");
+
+        let mid_part = Category::get(125).file_type(FileType::Json).mid_part();
+        assert_eq!(mid_part, "category?category_id=125&file_type=json&");
+    }
+
+    #[test]
+    fn build_appends_api_key() {
+        let req = Tags::all().sort_order(SortOrder::Desc).build(Some("abcd")).unwrap();
+        assert_eq!(
+            req.uri().unwrap().to_string(),
+            "https://api.stlouisfed.org/fred/tags?sort_order=desc&api_key=abcd",
+        );
+    }
+}
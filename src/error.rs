@@ -0,0 +1,131 @@
+/*!
+The crate's error type.
+*/
+
+use {
+    crate::DebugErr,
+    std::{fmt, time::Duration},
+};
+
+/**
+Errors returned by this crate. Every variant carries a [`DebugErr`] built with
+[`src!`](crate::src) where the error was raised, so the crate, file and line of the
+failure are kept alongside the message.
+```
+use fred_api::{build_request, send_request, Error, Lookup};
+
+# tokio_test::block_on(async {
+let dir = tempfile::TempDir::new().unwrap();
+let db = sled::open(dir.path()).unwrap();
+let req = build_request("series/observations?series_id=GNPCA&", Some("abcd")).unwrap();
+match send_request(&req, Lookup::CacheOnly, &db).await {
+    Err(Error::CacheMiss { mid_part, .. }) => assert!(mid_part.contains("GNPCA")),
+    _ => panic!("Should miss"),
+}
+# })
+```
+*/
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// A ``Lookup::CacheOnly`` request was not in the cache.
+    CacheMiss { mid_part: String, src: DebugErr },
+    /// FRED answered with an error status, for example a 400 for a bad ``series_id``.
+    /// ``code`` and ``message`` come from FRED's ``<error>`` body where there is one.
+    FredApi { status: u16, code: Option<u16>, message: String, attempts: u32, src: DebugErr },
+    /// FRED answered 429 Too Many Requests to the last attempt.
+    RateLimited { retry_after: Option<Duration>, attempts: u32, src: DebugErr },
+    /// The request or response failed in transit, such as a reset connection.
+    Transport { attempts: u32, src: DebugErr },
+    /// TLS could not be set up, for example without native root certificates.
+    Tls(DebugErr),
+    InvalidUri(DebugErr),
+    /// The response is not well-formed or could not be decoded.
+    Xml(DebugErr),
+    /// A requested attribute, or element text, is missing or empty.
+    MissingAttribute { tag: String, attribute: String, src: DebugErr },
+    /// The cache failed to read or write.
+    Cache(DebugErr),
+    /// The environment or an argument is unusable, such as an unset ``FRED_API_KEY``.
+    Config(DebugErr),
+}
+
+impl Error {
+
+    /**
+    The message and source location of the error.
+    */
+    pub fn src(&self) -> &DebugErr {
+        match self {
+            Error::CacheMiss { src, .. }
+            | Error::FredApi { src, .. }
+            | Error::RateLimited { src, .. }
+            | Error::Transport { src, .. }
+            | Error::MissingAttribute { src, .. } => src,
+            Error::Tls(src)
+            | Error::InvalidUri(src)
+            | Error::Xml(src)
+            | Error::Cache(src)
+            | Error::Config(src) => src,
+        }
+    }
+
+    pub fn msg(&self) -> &str { &self.src().msg }
+
+    /**
+    How many requests were made to FRED, for errors that come from FRED.
+    */
+    // test: error_records_attempts
+    pub fn attempts(&self) -> Option<u32> {
+        match self {
+            Error::FredApi { attempts, .. }
+            | Error::RateLimited { attempts, .. }
+            | Error::Transport { attempts, .. } => Some(*attempts),
+            _ => None,
+        }
+    }
+
+    // test: error_records_attempts
+    pub(crate) fn with_attempts(mut self, n: u32) -> Self {
+        match &mut self {
+            Error::FredApi { attempts, .. }
+            | Error::RateLimited { attempts, .. }
+            | Error::Transport { attempts, .. } => *attempts = n,
+            _ => {},
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    // test: error_records_attempts
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.src())?;
+        match self.attempts() {
+            Some(n) if n > 1 => write!(f, " Gave up after {n} attempts."),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    #[test]
+    fn error_records_attempts() {
+        let e = Error::Transport { attempts: 1, src: src!("Connection reset.") };
+        assert!(e.to_string().ends_with("Connection reset."));
+
+        let e = e.with_attempts(3);
+        assert_eq!(e.attempts(), Some(3));
+        assert_eq!(e.msg(), "Connection reset.");
+        assert!(e.to_string().ends_with("Connection reset. Gave up after 3 attempts."));
+        assert!(e.to_string().starts_with("[fred_api:src/error.rs:"));
+
+        let e = Error::Config(src!("FRED_API_KEY missing")).with_attempts(3);
+        assert_eq!(e.attempts(), None);
+    }
+}
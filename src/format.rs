@@ -0,0 +1,256 @@
+/*!
+The XML and JSON formats FRED serves.
+*/
+
+use {
+    crate::{
+        read_attributes, src, AttributeSpec, Attributes, DebugErr, Error, OptionalFieldIter,
+        Result,
+    },
+    quick_xml::{events::Event, reader::Reader},
+    std::{fmt, str::FromStr},
+};
+
+/**
+The format of a FRED response, chosen with the ``file_type`` parameter, see
+[`endpoints::FileType`](crate::endpoints::FileType). Reading JSON responses with
+[`models`](crate::models) needs the ``json`` feature.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Xml,
+    Json,
+}
+
+impl Format {
+
+    /**
+    The format of a response body. FRED's JSON bodies are objects, anything else is
+    taken to be XML.
+    */
+    // test: format_detects_json
+    pub fn detect(bytes: &[u8]) -> Self {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(b'{') => Format::Json,
+            _ => Format::Xml,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Format::Xml => "xml",
+            Format::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { f.write_str(self.as_str()) }
+}
+
+serde_via_str!(Format);
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "xml" => Ok(Format::Xml),
+            "json" => Ok(Format::Json),
+            _ => Err(Error::Config(src!("Unknown format '{s}'"))),
+        }
+    }
+}
+
+/**
+The ``code`` and ``message`` of a FRED error body, ``<error code message>`` in XML or
+``{"error_code", "error_message"}`` in JSON.
+*/
+// test: error_details_reads_both_formats
+pub(crate) fn error_details(body: &[u8]) -> Option<(Option<u16>, String)> {
+    match Format::detect(body) {
+        Format::Xml => {
+            let fields = vec!["code?", "message"];
+            let mut fields = OptionalFieldIter::new("error", fields, body.into()).next()?.ok()?;
+            let message = fields.pop()??;
+            Some((fields[0].as_deref().and_then(|code| code.parse().ok()), message))
+        },
+        Format::Json => json_error_details(body),
+    }
+}
+
+#[cfg(any(test, feature = "json"))]
+fn json_error_details(body: &[u8]) -> Option<(Option<u16>, String)> {
+    let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let message = value.get("error_message")?.as_str()?.to_string();
+    let code = value.get("error_code").and_then(|code| code.as_u64()).map(|code| code as u16);
+    Some((code, message))
+}
+
+#[cfg(not(any(test, feature = "json")))]
+fn json_error_details(_body: &[u8]) -> Option<(Option<u16>, String)> { None }
+
+/**
+The name and attributes of the root element. For JSON the name is empty and the
+attributes are the members of the top-level object that are not arrays or objects.
+*/
+// test: root_attributes_reads_both_formats
+pub(crate) fn root_attributes(bytes: &[u8]) -> Result<(String, Attributes)> {
+    if Format::detect(bytes) == Format::Json {
+        return Ok((String::new(), json_root(bytes)?));
+    }
+    let mut reader = Reader::from_reader(bytes);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(Event::Start(root)) | Ok(Event::Empty(root)) => {
+                let name = reader
+                    .decoder()
+                    .decode(root.name().as_ref())
+                    .map_err(|e| Error::Xml(src!("XML decoding error for tag: {e}")))?
+                    .to_string();
+                let attributes = read_attributes(&root, reader.decoder(), &AttributeSpec::ALL)?;
+                return Ok((name, attributes));
+            },
+            Ok(Event::Eof) => return Err(Error::Xml(src!("No root element"))),
+            Err(e) => return Err(Error::Xml(src!("XML parsing error: {e}"))),
+            _ => buf.clear(),
+        }
+    }
+}
+
+#[cfg(any(test, feature = "json"))]
+fn json_root(bytes: &[u8]) -> Result<Attributes> {
+    let value: serde_json::Value = serde_json::from_slice(bytes)
+        .map_err(|e| Error::Xml(src!("JSON parsing error: {e}")))?;
+    let object = value.as_object().ok_or(Error::Xml(src!("Expected a JSON object")))?;
+    Ok(json_attributes(object))
+}
+
+#[cfg(not(any(test, feature = "json")))]
+fn json_root(_bytes: &[u8]) -> Result<Attributes> {
+    Err(Error::Config(src!("Reading JSON responses needs the 'json' feature of fred_api")))
+}
+
+// Scalar members as text, leaving out nulls, arrays and objects.
+#[cfg(any(test, feature = "json"))]
+fn json_attributes(object: &serde_json::Map<String, serde_json::Value>) -> Attributes {
+    use serde_json::Value;
+
+    let attributes = object
+        .iter()
+        .filter_map(|(name, value)| {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => return None,
+            };
+            Some((name.clone(), value))
+        })
+        .collect();
+    Attributes(attributes)
+}
+
+/**
+The members of the array ``key`` of a JSON response, such as ``observations``, as
+attributes. Numbers and booleans are written as text and nulls are left out, so the
+rows read the same as the XML attributes.
+*/
+// test: json_rows_match_xml_attributes
+#[cfg(any(test, feature = "json"))]
+pub(crate) fn json_rows(bytes: &[u8], key: &str) -> Result<Vec<Attributes>> {
+    use serde_json::Value;
+
+    let value: Value = serde_json::from_slice(bytes)
+        .map_err(|e| Error::Xml(src!("JSON parsing error: {e}")))?;
+    let Some(rows) = value.get(key).and_then(Value::as_array) else {
+        return Ok(Vec::new());
+    };
+    rows.iter()
+        .map(|row| {
+            let object = row
+                .as_object()
+                .ok_or(Error::Xml(src!("Expected objects in JSON array '{key}'")))?;
+            Ok(json_attributes(object))
+        })
+        .collect()
+}
+
+/**
+The JSON array holding the elements named ``tag``, which FRED names by adding ``s``, so
+``series`` are in ``seriess``, or ``ies`` in place of a final ``y``.
+*/
+// test: json_rows_match_xml_attributes
+pub(crate) fn json_key(tag: &str) -> String {
+    match tag.strip_suffix('y') {
+        Some(stem) => format!("{stem}ies"),
+        None => format!("{tag}s"),
+    }
+}
+
+#[cfg(not(any(test, feature = "json")))]
+pub(crate) fn json_rows(_bytes: &[u8], _key: &str) -> Result<Vec<Attributes>> {
+    Err(Error::Config(src!("Reading JSON responses needs the 'json' feature of fred_api")))
+}
+
+#[cfg(test)]
+mod test {
+    use {super::*, crate::*};
+
+    #[test]
+    fn format_detects_json() {
+        assert_eq!(Format::detect(b" \n{\"seriess\":[]}"), Format::Json);
+        assert_eq!(Format::detect(b"<?xml version=\"1.0\"?><seriess/>"), Format::Xml);
+        assert_eq!(Format::detect(b""), Format::Xml);
+        assert_eq!("json".parse::<Format>().unwrap(), Format::Json);
+        assert!("csv".parse::<Format>().is_err());
+    }
+
+    #[test]
+    fn error_details_reads_both_formats() {
+        let json = br#"{"error_code":400,"error_message":"Bad Request.  The series does not exist."}"#;
+        assert_eq!(
+            error_details(json),
+            Some((Some(400), "Bad Request.  The series does not exist.".to_string())),
+        );
+        let xml = br#"<error code="400" message="Bad Request.  The series does not exist."/>"#;
+        assert_eq!(error_details(xml), error_details(json));
+        assert_eq!(
+            error_details(br#"<error message="Bad category"/>"#),
+            Some((None, "Bad category".to_string())),
+        );
+        assert_eq!(error_details(b"Not XML"), None);
+        assert_eq!(error_details(b"{not json"), None);
+    }
+
+    #[test]
+    fn json_rows_match_xml_attributes() {
+        let json = br#"{"count":1,"categories":[{"id":125,"name":"Trade Balance","parent_id":13,"notes":null}]}"#;
+        let rows = json_rows(json, "categories").unwrap();
+        let xml = br#"<categories><category id="125" name="Trade Balance" parent_id="13"/></categories>"#;
+        let from_xml = AttributeIter::new("category", xml[..].into()).next().unwrap().unwrap();
+        assert_eq!(rows, vec![from_xml]);
+        assert!(json_rows(json, "seriess").unwrap().is_empty());
+        assert!(matches!(json_rows(b"[", "categories"), Err(Error::Xml(_))));
+        assert_eq!(json_key("category"), "categories");
+        assert_eq!(json_key("series"), "seriess");
+        assert_eq!(json_key("release_date"), "release_dates");
+    }
+
+    #[test]
+    fn root_attributes_reads_both_formats() {
+        let xml = br#"<?xml version="1.0" encoding="utf-8" ?>
+<!-- comment -->
+<tags realtime_start="2013-08-14" count="2"><tag name="usa"/></tags>"#;
+        let (tag, root) = root_attributes(xml).unwrap();
+        assert_eq!((tag.as_str(), root.get("count")), ("tags", Some("2")));
+
+        let json = br#"{"realtime_start":"2013-08-14","count":2,"tags":[{"name":"usa"}]}"#;
+        let (tag, root) = root_attributes(json).unwrap();
+        assert_eq!(tag, "");
+        assert_eq!(root.names().collect::<Vec<_>>(), ["count", "realtime_start"]);
+        assert!(root_attributes(b"").is_err());
+    }
+}
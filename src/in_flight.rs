@@ -0,0 +1,45 @@
+/*!
+Sharing one fetch from FRED between callers that ask for the same request at once.
+*/
+
+use {
+    crate::Result,
+    sled::IVec,
+    std::{
+        collections::HashMap,
+        future::Future,
+        sync::{Arc, Mutex},
+    },
+    tokio::sync::OnceCell,
+};
+
+type Call = Arc<OnceCell<Result<IVec>>>;
+
+/**
+The fetches under way, by [`store_id`](crate::CacheStore::store_id) and cache key, as a
+fetch writes to one store only. Clones share them.
+*/
+#[derive(Clone, Default)]
+pub(crate) struct InFlight(Arc<Mutex<HashMap<(usize, IVec), Call>>>);
+
+impl InFlight {
+
+    /**
+    The result of ``fetch``, or of the fetch of ``key`` already under way. If the caller
+    running the fetch gives up, one of those waiting runs its own.
+    */
+    // test: concurrent_misses_share_one_fetch
+    pub(crate) async fn run(
+        &self,
+        key: (usize, IVec),
+        fetch: impl Future<Output = Result<IVec>>) -> Result<IVec>
+    {
+        let call = self.0.lock().unwrap().entry(key.clone()).or_default().clone();
+        let result = call.get_or_init(|| fetch).await.clone();
+        let mut calls = self.0.lock().unwrap();
+        if calls.get(&key).is_some_and(|current| Arc::ptr_eq(current, &call)) {
+            calls.remove(&key);
+        }
+        result
+    }
+}
@@ -1,14 +1,15 @@
 /*!
 `fred_api` makes requests to [FRED](https://fred.stlouisfed.org/) to download
 economic data, caching it to a data-store so that repeated requests to FRED can be
-avoided.
+avoided. The data-store is a ``sled::Db`` or any other [`CacheStore`], such as an
+[`FsStore`] that several processes can share.
 
 ### Requirements
 
 1. ``FRED_API_KEY`` environment variable can be set or supplied directly.
 
 2. ``FRED_CACHE`` is the directory to place the cache. It can also be set as an
-environment variable or supplied directly.
+   environment variable or supplied directly.
 
 ### Code Example
 
@@ -33,10 +34,22 @@ pub async fn main() {
     // from the environment variable FRED_API_KEY.
     let req = build_request("series/observations?series_id=GNPCA&", None).unwrap();
 
-    // Lookup options are Lookup::FredOnly, Lookup::CacheOnly or
-    // Lookup::FredOnCacheMiss. Successful FRED responses are always cached.
+    // Alternatively, the builders in `endpoints` produce the same request and check
+    // the path and parameter names at compile time.
+    let req = endpoints::Series::observations("GNPCA").build(None).unwrap();
+
+    // Lookup options are Lookup::FredOnly, Lookup::CacheOnly,
+    // Lookup::FredOnCacheMiss, Lookup::FredIfOlderThan(duration), Lookup::FredIfStale
+    // or Lookup::StaleWhileRevalidate. Successful FRED responses are always cached.
     let bytes: IVec = send_request(&req, Lookup::FredOnCacheMiss, &cache).await.unwrap();
 
+    // `models` parses responses into typed records. FRED's "." for a missing value
+    // is `None`.
+    let obs = models::observations(bytes.clone()).unwrap();
+    assert_eq!("1971-04-01", obs[0].date.to_string());
+    assert_eq!(Some(0.850603488248666), obs[0].value);
+
+    // `FieldIter` reads any attributes of any tag as strings.
     let mut field_iter = FieldIter::new("observation", vec!("date", "value"), bytes);
     let fields = field_iter.next().unwrap().unwrap();
     assert_eq!("1971-04-01", fields[0]);
@@ -46,21 +59,87 @@ pub async fn main() {
 */
 
 use {
-    http::{StatusCode, uri::Uri},
-    http_body_util::{BodyExt, Empty},
-    hyper::body::Bytes,
-    hyper_util::{client::legacy::Client, rt::TokioExecutor},
-    hyper_rustls::ConfigBuilderExt,
-    quick_xml::{events::{Event}, reader::Reader},
-    rustls::{version::TLS13},
-    sled::{Db, IVec},
-    std::{fmt, env, io::Cursor, path::PathBuf, str::FromStr},
+    http::uri::Uri,
+    quick_xml::{
+        encoding::{Decoder, EncodingError},
+        escape::resolve_predefined_entity,
+        events::{BytesStart, Event},
+        reader::Reader,
+    },
+    sled::IVec,
+    std::{fmt, env, io::Cursor, path::PathBuf, str::FromStr, sync::Arc, time::Duration},
 };
 
-static BASE_URI: &'static str = "https://api.stlouisfed.org/fred";
+// Serde through `Display` and `FromStr`, for types with a text form.
+macro_rules! serde_via_str {
+    ($name:ty) => {
+        #[cfg(any(test, feature = "serde"))]
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(
+                &self,
+                serializer: S) -> std::result::Result<S::Ok, S::Error>
+            {
+                serializer.collect_str(self)
+            }
+        }
+
+        #[cfg(any(test, feature = "serde"))]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(
+                deserializer: D) -> std::result::Result<Self, D::Error>
+            {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(|e: Error| serde::de::Error::custom(e.msg()))
+            }
+        }
+    };
+}
+
+mod batch;
+mod cache;
+mod client;
+pub mod endpoints;
+mod error;
+mod format;
+mod in_flight;
+mod lru;
+pub mod models;
+mod paging;
+mod rate_limit;
+mod retry;
+mod snapshot;
+#[cfg(any(test, feature = "sqlite"))]
+mod sqlite;
+mod store;
+mod ttl;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+
+pub use {
+    batch::Batch,
+    cache::{lookup_entry, previous_entry, CacheEntry, EntryMeta},
+    client::FredClient,
+    error::Error,
+    format::Format,
+    lru::{LruStats, LruStore},
+    paging::{PageInfo, Pages, Rows},
+    rate_limit::RateLimiter,
+    retry::RetryPolicy,
+    snapshot::SnapshotStore,
+    store::{CacheStore, FsStore, MemoryStore},
+    ttl::TtlPolicy,
+};
+
+/**
+The default base of every request Uri, see [`FredClient::with_base_uri`] to change it.
+*/
+pub static BASE_URI: &str = "https://api.stlouisfed.org/fred";
+
+#[cfg(any(test, feature = "sqlite"))]
+pub use sqlite::SqliteStore;
 
 pub use debug_err::{src, DebugErr};
-pub type Result<T> = std::result::Result<T, DebugErr>;
+pub type Result<T> = std::result::Result<T, Error>;
 
 /**
 Retrieves ``FRED_CACHE`` environment variable if set or fails.
@@ -71,7 +150,9 @@ pub fn fred_cache(opt_path: Option<&str>) -> Result<PathBuf> {
         None => {
             env::var("FRED_CACHE")
                 .map(PathBuf::from)
-                .map_err(|e| src!("Failed to read FRED_CACHE with error: '{e}'."))
+                .map_err(|e| {
+                    Error::Config(src!("Failed to read FRED_CACHE with error: '{e}'."))
+                })
         },
     }
 }
@@ -91,75 +172,19 @@ pub fn build_request(mid_part: &str, api_key: Option<&str>) -> Result<RequestSpe
 }
 
 /**
-Non-async request to cache only.
+Non-async request to cache only. See [`lookup_entry`] for the metadata as well.
 */
 // test: cache_request_hit_and_miss_works
-pub fn cache_request(req: &RequestSpec, db: &Db) -> Result<Option<IVec>> {
-    // let key: IVec = req.clone().into();
-    let ivec = match db.get(req.ivec()) {
-        Ok(Some(ivec)) => ivec,
-        Ok(None) => { return Ok(None) },
-        // Failed to induce an error in Sled using Linux permissions or disk corruption.
-        Err(e) => Err(src!("{e}"))?,
-    };
-    return Ok(Some(ivec));
-}
-
-/**
-Request to FRED bypassing cache.
-*/
-// test: fred_request_should_return_err_on_bad_request
-async fn fred_request(req: &RequestSpec, db: &Db) -> Result<IVec> {
-    let tls = rustls::ClientConfig::builder_with_protocol_versions(&[&TLS13])
-        .with_native_roots().map_err(|e| src!("{e}"))?
-        .with_no_client_auth();
-
-    let https = hyper_rustls::HttpsConnectorBuilder::new()
-        .with_tls_config(tls)
-        .https_only()
-        .enable_http2()
-        .build();
-
-    let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new()).build(https);
-
-    let fut = async move {
-        let res = client
-            .get(req.uri()?)
-            .await
-            .map_err(|err| src!("Request failed with error: {err}."))?;
-
-        let status = res.status();
-
-        let body = res
-            .into_body()
-            .collect()
-            .await
-            .map_err(|err| src!("Could not get body: {err}."))?
-            .to_bytes();
-
-        if status == StatusCode::OK {
-            write_to_cache(req, body.as_ref(), db)?;
-            let ivec = db
-                .get(req.ivec())
-                .map_err(|e| src!("{e}"))?
-                .ok_or(src!("Just inserted but not found"))?;
-            Ok(ivec)
-        } else {
-            let mut field_iter = FieldIter::new("error", vec!["message"], body.as_ref().into())
-                .take_while(|result| result.is_ok()).map(|result| result.unwrap());
-            
-            let message = field_iter.next()
-                .and_then(|fields| fields.first().cloned())
-                .unwrap_or("Unknown error".to_string());
-            Err(src!("FRED API error: '{message}'"))
-        }
-    };
-
-    fut.await
+pub fn cache_request(req: &RequestSpec, store: &dyn CacheStore) -> Result<Option<IVec>> {
+    Ok(store.get_entry(&req.ivec())?.map(|entry| entry.body))
 }
 
 /**
 Send a request to FRED or the cache, using the lookup method to determine procedure.
+All calls share one connection pool and one [`RateLimiter`] at FRED's limit of 120
+requests per minute, and retry transient failures with the default [`RetryPolicy`]; use
+[`FredClient`] to hold the API key and cache as well. Calls made at the same time for one
+request and one store, see [`CacheStore::store_id`], share one fetch.
 ```no_run
 use fred_api::{build_request, fred_cache, Lookup, send_request};
 
@@ -173,47 +198,51 @@ let bytes = send_request(&req, Lookup::CacheOnly, &db).await.unwrap();
 pub async fn send_request(
     req: &RequestSpec,
     lookup: Lookup,
-    db: &Db) -> Result<IVec> 
+    store: &(impl CacheStore + Clone + 'static)) -> Result<IVec>
 {
-    match lookup {
-        Lookup::FredOnCacheMiss => {
-            match cache_request(req, db) {
-                Ok(None) => { return fred_request(req, db).await },
-                Ok(Some(bytes)) => return Ok(bytes),
-                Err(e) => return Err(e),
-            }
-        },
-        Lookup::CacheOnly => {
-            match cache_request(req, db) {
-                Ok(Some(bytes)) => Ok(bytes),
-                Ok(None) => Err(src!(
-                    "Cache only request (mid-part '{}') failed",
-                    req.mid_part()
-                ))?,
-                Err(e) => Err(e),
-            }
-        },
-        Lookup::FredOnly => {
-            match fred_request(req, db).await {
-                Ok(bytes) => Ok(bytes),
-                Err(e) => Err(e),
-            }
-        },
-    }
+    FredClient::shared(Arc::new(store.clone()))?.send(req, lookup).await
 }
 
 /*
-Write a FRED response into the caching database.
+Write a FRED response and its metadata into the cache, replacing any entry for the
+request. With ``keep_previous`` the replaced entry becomes the previous version, see
+[`previous_entry`]. Returns the bytes written.
 */
-fn write_to_cache(req: &RequestSpec, bytes: &[u8], db: &Db) -> Result<()> {
-    let key: IVec = req.ivec();
-    let value: IVec = bytes.as_ref().into();
-    match db.contains_key(&key) {
-        Ok(true) => {},
-        Ok(false) => { if let Err(err) = db.insert(key, value) { Err(src!("{err}"))? }},
-        Err(err) => Err(src!("{err}"))?,
+pub(crate) fn write_to_cache(
+    req: &RequestSpec,
+    bytes: &[u8],
+    meta: &EntryMeta,
+    keep_previous: bool,
+    store: &dyn CacheStore) -> Result<IVec>
+{
+    let entry = CacheEntry { body: bytes.into(), meta: Some(meta.clone()) };
+    store.put_entry(&req.ivec(), &entry, keep_previous)?;
+    Ok(entry.body)
+}
+
+/**
+Rewrite the keys of a cache written before keys were canonical, see
+[`canonical_mid_part`]. Where an old key and an existing canonical key collide, the
+canonical entry is kept. Returns the number of old keys removed.
+*/
+// test: migrate_cache_keys_works
+pub fn migrate_cache_keys(store: &dyn CacheStore) -> Result<usize> {
+    let mut migrated = 0;
+    for item in store.entries() {
+        let (key, entry) = item?;
+        let Ok(mid_part) = std::str::from_utf8(&key) else { continue };
+        let canonical = canonical_mid_part(mid_part);
+        if canonical.as_bytes() == &*key { continue }
+
+        // The canonical entry is written before the old one goes.
+        if store.get_entry(canonical.as_bytes())?.is_none() {
+            store.put_entry(canonical.as_bytes(), &entry, false)?;
+        }
+        store.remove_entry(&key)?;
+        migrated += 1;
     }
-    Ok(())
+    store.flush_entries()?;
+    Ok(migrated)
 }
 
 /**
@@ -237,7 +266,9 @@ impl RequestSpec {
         let key = match api_key {
             Some(key) => key.to_string(),
             None => env::var("FRED_API_KEY")
-                .map_err(|err| src!("FRED_API_KEY environment variable missing: {err}"))?,
+                .map_err(|err| {
+                    Error::Config(src!("FRED_API_KEY environment variable missing: {err}"))
+                })?,
         };
         Ok(RequestSpec {
             mid_part: mid_part.to_string(),
@@ -247,18 +278,101 @@ impl RequestSpec {
 
     // test: uri_request_spec_edge_case
     pub fn uri(&self) -> Result<Uri> {
-        let s = &format!("{}/{}api_key={}", BASE_URI, self.mid_part, self.key);
-        Ok(Uri::from_str(s).map_err(|e| src!("{e}"))?)
+        self.uri_with_base(BASE_URI)
+    }
+
+    /**
+    The request Uri on a base other than [`BASE_URI`], such as a local mock server.
+    */
+    // test: uri_with_base_works
+    pub fn uri_with_base(&self, base: &str) -> Result<Uri> {
+        let s = &format!("{}/{}api_key={}", base.trim_end_matches('/'), self.mid_part, self.key);
+        Uri::from_str(s).map_err(|e| Error::InvalidUri(src!("{e}")))
     }
 
     pub fn mid_part(&self) -> String { self.mid_part.clone() }
 
+    /**
+    The normalized mid-part used as the cache key, see [`canonical_mid_part`].
+    */
+    // test: canonical_key_ignores_parameter_order
+    pub fn canonical(&self) -> String { canonical_mid_part(&self.mid_part) }
+
     // test: ivec_as_key
     pub fn ivec(&self) -> IVec {
-        self.mid_part.as_bytes().into()
+        self.canonical().as_bytes().into()
     }
 
     pub fn has_api_key(&self) -> bool { !self.key.is_empty() }
+
+    /*
+    The same request with parameter `name` set to `value`, replacing any earlier value.
+    */
+    // test: pages_follow_offset
+    pub(crate) fn with_param(&self, name: &str, value: &str) -> RequestSpec {
+        let (path, query) = self.mid_part.split_once('?').unwrap_or((&self.mid_part, ""));
+        let mut mid_part = format!("{path}?");
+        for param in query.split('&') {
+            if !param.is_empty() && param.split('=').next() != Some(name) {
+                mid_part.push_str(param);
+                mid_part.push('&');
+            }
+        }
+        mid_part.push_str(&format!("{name}={}&", endpoints::encode_value(value)));
+        RequestSpec { mid_part, key: self.key.clone() }
+    }
+
+    /**
+    The format requested with ``file_type``, XML unless ``file_type=json``.
+    */
+    // test: canonical_key_ignores_parameter_order
+    pub fn format(&self) -> Format {
+        let canonical = self.canonical();
+        let query = canonical.split_once('?').map_or("", |(_, query)| query);
+        match query.split('&').any(|param| param == "file_type=json") {
+            true => Format::Json,
+            false => Format::Xml,
+        }
+    }
+}
+
+/**
+Normalize a mid-part so that equivalent requests produce the same cache key. The path
+loses any surrounding ``/``, parameters are percent-decoded and re-encoded in one
+form, sorted, ``api_key`` is dropped and the default ``file_type=xml`` is left out.
+```
+use fred_api::canonical_mid_part;
+
+assert_eq!(
+    canonical_mid_part("series/observations?units=pch&series_id=GNPCA&file_type=xml&"),
+    "series/observations?series_id=GNPCA&units=pch&",
+);
+```
+*/
+// test: canonical_key_ignores_parameter_order
+pub fn canonical_mid_part(mid_part: &str) -> String {
+    let (path, query) = mid_part.split_once('?').unwrap_or((mid_part, ""));
+    let mut params: Vec<(String, String)> = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (endpoints::decode_value(name), endpoints::decode_value(value))
+        })
+        .filter(|(name, value)| {
+            name != "api_key" && !(name == "file_type" && value == "xml")
+        })
+        .collect();
+    params.sort();
+
+    let mut s = format!("{}?", path.trim_matches('/'));
+    for (name, value) in params {
+        s.push_str(&endpoints::encode_value(&name));
+        s.push('=');
+        s.push_str(&endpoints::encode_value(&value));
+        s.push('&');
+    }
+    s
 }
 
 impl fmt::Display for RequestSpec {
@@ -266,6 +380,19 @@ impl fmt::Display for RequestSpec {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result { write!(f, "{}", self.mid_part) }
 }
 
+/**
+Serializes as [`StoredRequest`], without the API key.
+*/
+#[cfg(any(test, feature = "serde"))]
+impl serde::Serialize for RequestSpec {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S) -> std::result::Result<S::Ok, S::Error>
+    {
+        StoredRequest::from(self).serialize(serializer)
+    }
+}
+
 impl std::fmt::Debug for RequestSpec {
     // test: request_spec_hides_api_key    
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -277,114 +404,574 @@ impl std::fmt::Debug for RequestSpec {
     }
 }
 
+/**
+Iterates over the elements named ``tag``, yielding the values of ``fields`` for each.
+A missing or empty field is an error, after which iteration stops unless
+[`lenient`](FieldIter::lenient).
+
+A field is an attribute of the element, or a selector relative to the element:
+
+| Field | Value |
+|---|---|
+| ``name`` or ``@name`` | attribute ``name`` |
+| ``.`` | text of the element |
+| ``./child`` or ``child/grandchild`` | text of the first such descendant |
+| ``child@name`` or ``child/grandchild@name`` | attribute ``name`` of the first such descendant |
+```
+use fred_api::FieldIter;
+
+let xml = r#"<element element_id="12886" name="Total nonfarm">
+    <release_id>50</release_id>
+    <children><element element_id="12887" name="Total private"/></children>
+</element>"#;
+let fields = vec!["name", "./release_id", "children/element@name"];
+let row = FieldIter::new("element", fields, xml.as_bytes().into()).next().unwrap().unwrap();
+assert_eq!(row, vec!["Total nonfarm", "50", "Total private"]);
+```
+Elements nested in a matched element are not matched themselves when a field selects
+text or descendants. Attribute values are returned as written, so ``&amp;`` stays
+``&amp;``, while [`OptionalFieldIter`] and [`AttributeIter`] unescape them. Only the
+attributes named by ``fields`` are read, so a malformed attribute that is not asked for
+does not fail the row.
+*/
 #[derive(Clone, Debug)]
 pub struct FieldIter {
-    reader: Reader<Cursor<IVec>>, // Use Cursor<IVec>
-    fields: Vec<String>,
+    reader: TagReader,
+    fields: Vec<Selector>,
+}
+
+impl FieldIter {
+    // test: field_iter_selects_child_elements
+    pub fn new(tag: &str, fields: Vec<&str>, ivec: IVec) -> Self {
+        let fields: Vec<_> = fields.into_iter().map(Selector::new).collect();
+        let mut reader = TagReader::new(tag, ivec, Some(&fields));
+        reader.attributes.unescape = false;
+        FieldIter { reader, fields }
+    }
+
+    /**
+    Keep going after a bad row. Malformed XML still stops iteration.
+    */
+    // test: lenient_field_iter_continues_past_bad_rows
+    pub fn lenient(mut self) -> Self {
+        self.reader.lenient = true;
+        self
+    }
+}
+
+impl Iterator for FieldIter {
+    type Item = Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let fields = &self.fields;
+        self.reader.next_with(|tag, element| {
+            fields.iter().map(|field| required_field(tag, &element, field)).collect()
+        })
+    }
+}
+
+/**
+As [`FieldIter`], but fields ending in ``?`` may be missing or empty and yield ``None``.
+```
+use fred_api::OptionalFieldIter;
+
+let xml = r#"<releases>
+    <release id="9" name="Retail Sales" link="http://www.census.gov/retail/"/>
+    <release id="10" name="Consumer Price Index"/>
+</releases>"#;
+let rows: Vec<_> = OptionalFieldIter::new("release", vec!["id", "link?"], xml.as_bytes().into())
+    .collect::<Result<_, _>>()
+    .unwrap();
+assert_eq!(rows[0], vec![Some("9".to_string()), Some("http://www.census.gov/retail/".to_string())]);
+assert_eq!(rows[1], vec![Some("10".to_string()), None]);
+```
+*/
+#[derive(Clone, Debug)]
+pub struct OptionalFieldIter {
+    reader: TagReader,
+    // The field and whether it is optional.
+    fields: Vec<(Selector, bool)>,
+}
+
+impl OptionalFieldIter {
+    // test: optional_field_iter_works
+    pub fn new(tag: &str, fields: Vec<&str>, ivec: IVec) -> Self {
+        let fields: Vec<_> = fields
+            .into_iter()
+            .map(|field| match field.strip_suffix('?') {
+                Some(field) => (Selector::new(field), true),
+                None => (Selector::new(field), false),
+            })
+            .collect();
+        let selectors: Vec<_> = fields.iter().map(|(field, _)| field.clone()).collect();
+        OptionalFieldIter { reader: TagReader::new(tag, ivec, Some(&selectors)), fields }
+    }
+
+    /**
+    Keep going after a bad row. Malformed XML still stops iteration.
+    */
+    pub fn lenient(mut self) -> Self {
+        self.reader.lenient = true;
+        self
+    }
+}
+
+impl Iterator for OptionalFieldIter {
+    type Item = Result<Vec<Option<String>>>;
+
+    // test: optional_field_iter_works
+    fn next(&mut self) -> Option<Self::Item> {
+        let fields = &self.fields;
+        self.reader.next_with(|tag, element| {
+            fields
+                .iter()
+                .map(|(field, optional)| match optional {
+                    true => Ok(element.select(field).filter(|v| !v.is_empty()).map(str::to_string)),
+                    false => required_field(tag, &element, field).map(Some),
+                })
+                .collect()
+        })
+    }
+}
+
+/**
+Every attribute of each element named ``tag``, in document order.
+```
+use fred_api::AttributeIter;
+
+let xml = r#"<category id="125" name="Trade Balance" parent_id="13"/>"#;
+let attributes = AttributeIter::new("category", xml.as_bytes().into()).next().unwrap().unwrap();
+assert_eq!(attributes.get("name"), Some("Trade Balance"));
+assert_eq!(attributes.names().collect::<Vec<_>>(), vec!["id", "name", "parent_id"]);
+```
+*/
+#[derive(Clone, Debug)]
+pub struct AttributeIter {
+    reader: TagReader,
+}
+
+impl AttributeIter {
+    // test: attribute_iter_keeps_order
+    pub fn new(tag: &str, ivec: IVec) -> Self {
+        AttributeIter { reader: TagReader::new(tag, ivec, None) }
+    }
+
+    /**
+    Keep going after a bad row. Malformed XML still stops iteration.
+    */
+    pub fn lenient(mut self) -> Self {
+        self.reader.lenient = true;
+        self
+    }
+}
+
+impl Iterator for AttributeIter {
+    type Item = Result<Attributes>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.reader.next_with(|_, element| Ok(element.attributes))
+    }
+}
+
+/**
+The unescaped attributes of an element as name and value pairs, in document order.
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct Attributes(Vec<(String, String)>);
+
+impl Attributes {
+
+    /**
+    The value of an attribute, which may be empty.
+    */
+    // test: attribute_iter_keeps_order
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    pub fn len(&self) -> usize { self.0.len() }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+}
+
+impl IntoIterator for Attributes {
+    type Item = (String, String);
+    type IntoIter = std::vec::IntoIter<(String, String)>;
+
+    fn into_iter(self) -> Self::IntoIter { self.0.into_iter() }
+}
+
+fn required_field(tag: &str, element: &Element, field: &Selector) -> Result<String> {
+    let problem = match element.select(field) {
+        Some(value) if !value.is_empty() => return Ok(value.to_string()),
+        Some(_) => "Empty",
+        None => "Missing",
+    };
+    Err(Error::MissingAttribute {
+        tag: tag.to_string(),
+        attribute: field.field.clone(),
+        src: src!("{} attribute '{}' in tag '{}'", problem, field.field, tag),
+    })
+}
+
+/*
+A field of `FieldIter`, see its documentation for the syntax.
+*/
+#[derive(Clone, Debug)]
+struct Selector {
+    field: String,
+    path: Vec<String>,
+    // The text of the selected element when `None`.
+    attribute: Option<String>,
+}
+
+impl Selector {
+    fn new(field: &str) -> Self {
+        if !(field == "." || field.contains(['/', '@'])) {
+            let attribute = Some(field.to_string());
+            return Selector { field: field.to_string(), path: Vec::new(), attribute };
+        }
+        let (path, attribute) = match field.rsplit_once('@') {
+            Some((path, attribute)) => (path, Some(attribute.to_string())),
+            None => (field, None),
+        };
+        let path = path
+            .split('/')
+            .filter(|name| !name.is_empty() && *name != ".")
+            .map(str::to_string)
+            .collect();
+        Selector { field: field.to_string(), path, attribute }
+    }
+
+    fn needs_subtree(&self) -> bool { !self.path.is_empty() || self.attribute.is_none() }
+}
+
+/*
+A matched element, with its descendants and text only when a selector needs them.
+*/
+#[derive(Clone, Debug, Default)]
+struct Element {
+    name: String,
+    attributes: Attributes,
+    text: String,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn read(
+        bytes_start: &BytesStart,
+        decoder: Decoder,
+        spec: &AttributeSpec) -> Result<Self>
+    {
+        let name = decoder
+            .decode(bytes_start.name().as_ref())
+            .map_err(|e| Error::Xml(src!("XML decoding error for tag: {e}")))?
+            .to_string();
+        Ok(Element {
+            name,
+            attributes: read_attributes(bytes_start, decoder, spec)?,
+            ..Element::default()
+        })
+    }
+
+    fn select(&self, selector: &Selector) -> Option<&str> {
+        let mut element = self;
+        for name in &selector.path {
+            element = element.children.iter().find(|child| &child.name == name)?;
+        }
+        match &selector.attribute {
+            Some(attribute) => element.attributes.get(attribute),
+            None => Some(&element.text),
+        }
+    }
+}
+
+/*
+Reads each element named `tag`, shared by the iterators above.
+*/
+#[derive(Clone, Debug)]
+struct TagReader {
+    reader: Reader<Cursor<IVec>>,
     tag: String,
     buf: Vec<u8>,
     has_errored: bool,
+    lenient: bool,
+    // Whether to read the text and descendants of matched elements.
+    subtree: bool,
+    attributes: AttributeSpec,
 }
 
-impl FieldIter {
-    pub fn new(tag: &str, fields: Vec<&str>, ivec: IVec) -> Self {
-        let tag = tag.to_string();
-        let fields = fields.into_iter().map(|s| s.to_string()).collect();
-        let mut reader = Reader::from_reader(Cursor::new(ivec.clone()));
+impl TagReader {
+    // Reads what `fields` select, or every attribute of matched elements without them.
+    fn new(tag: &str, ivec: IVec, fields: Option<&[Selector]>) -> Self {
+        let mut reader = Reader::from_reader(Cursor::new(ivec));
         reader.config_mut().trim_text(true);
-        FieldIter {
+        TagReader {
             reader,
-            fields,
-            tag,
+            tag: tag.to_string(),
             buf: Vec::new(),
             has_errored: false,
+            lenient: false,
+            subtree: fields.into_iter().flatten().any(Selector::needs_subtree),
+            attributes: AttributeSpec {
+                wanted: fields.map(|fields| {
+                    fields.iter().filter_map(|field| field.attribute.clone()).collect()
+                }),
+                unescape: true,
+            },
         }
     }
-}
-
-impl Iterator for FieldIter {
-    type Item = Result<Vec<String>>;
 
-    fn next(&mut self) -> Option<Self::Item> {
+    // The next element passed through `row`. Errors end iteration unless lenient,
+    // where only errors reading the XML do.
+    fn next_with<T>(
+        &mut self,
+        row: impl FnOnce(&str, Element) -> Result<T>) -> Option<Result<T>>
+    {
         if self.has_errored {
             return None;
         }
         loop {
             self.buf.clear();
-            match self.reader.read_event_into(&mut self.buf) {
-                Ok(Event::Start(bytes_start)) | Ok(Event::Empty(bytes_start))
-                    if bytes_start.name().as_ref() == self.tag.as_bytes() => {
-                    let mut row = Vec::with_capacity(self.fields.len());
-                    for field in &self.fields {
-                        let attr_value = bytes_start
-                            .attributes()
-                            .filter_map(std::result::Result::ok)
-                            .find(|a| a.key.as_ref() == field.as_bytes());
-
-                        match attr_value {
-                            Some(a) => match self.reader.decoder().decode(&a.value) {
-                                Ok(value) => {
-                                    if value.is_empty() {
-                                        self.has_errored = true;
-                                        return Some(Err(src!(
-                                            "Empty attribute '{}' in tag '{}'",
-                                            field,
-                                            self.tag
-                                        )));
-                                    }
-                                    row.push(value.to_string());
-                                }
-                                Err(e) => {
-                                    self.has_errored = true;
-                                    return Some(Err(src!(
-                                        "XML decoding error for attribute '{}': {}",
-                                        field,
-                                        e
-                                    )));
-                                }
-                            },
-                            None => {
-                                self.has_errored = true;
-                                return Some(Err(src!(
-                                    "Missing attribute '{}' in tag '{}'",
-                                    field,
-                                    self.tag
-                                )));
-                            }
-                        }
-                    }
-                    return Some(Ok(row));
+            let decoder = self.reader.decoder();
+            let tag = self.tag.as_bytes();
+            let (element, has_children) = match self.reader.read_event_into(&mut self.buf) {
+                Ok(Event::Start(bytes_start)) if bytes_start.name().as_ref() == tag => {
+                    (Element::read(&bytes_start, decoder, &self.attributes), true)
+                }
+                Ok(Event::Empty(bytes_start)) if bytes_start.name().as_ref() == tag => {
+                    (Element::read(&bytes_start, decoder, &self.attributes), false)
                 }
                 Ok(Event::Eof) => return None,
                 Err(e) => {
                     self.has_errored = true;
-                    return Some(Err(src!("XML parsing error: {e}")));
+                    return Some(Err(Error::Xml(src!("XML parsing error: {e}"))));
                 }
-                _ => (),
+                _ => continue,
+            };
+            let result = element
+                .and_then(|element| match has_children && self.subtree {
+                    true => self.read_subtree(element).inspect_err(|_| self.has_errored = true),
+                    false => Ok(element),
+                })
+                .and_then(|element| row(&self.tag, element));
+            self.has_errored |= result.is_err() && !self.lenient;
+            return Some(result);
+        }
+    }
+
+    // Reads the text and descendants of `element`, whose start tag was just read.
+    fn read_subtree(&mut self, element: Element) -> Result<Element> {
+        // Text is trimmed once complete, as entities split it into several events.
+        self.reader.config_mut().trim_text(false);
+        let spec = &self.attributes;
+        let result = read_children(&mut self.reader, &mut self.buf, spec, element);
+        self.reader.config_mut().trim_text(true);
+        result
+    }
+}
+
+fn read_children(
+    reader: &mut Reader<Cursor<IVec>>,
+    buf: &mut Vec<u8>,
+    spec: &AttributeSpec,
+    element: Element) -> Result<Element>
+{
+    let decoder = reader.decoder();
+    let decode_err = |e: EncodingError| Error::Xml(src!("XML decoding error for text: {e}"));
+    let mut open = vec![element];
+    loop {
+        buf.clear();
+        let event = reader
+            .read_event_into(buf)
+            .map_err(|e| Error::Xml(src!("XML parsing error: {e}")))?;
+        let parent = open.last_mut().expect("The matched element is open");
+        match event {
+            Event::Start(bytes_start) => {
+                open.push(Element::read(&bytes_start, decoder, spec)?)
             }
+            Event::Empty(bytes_start) => {
+                parent.children.push(Element::read(&bytes_start, decoder, spec)?)
+            }
+            Event::Text(text) => parent.text.push_str(&text.xml_content().map_err(decode_err)?),
+            Event::CData(data) => parent.text.push_str(&data.decode().map_err(decode_err)?),
+            Event::GeneralRef(entity) => {
+                let c = entity
+                    .resolve_char_ref()
+                    .map_err(|e| Error::Xml(src!("XML character reference error: {e}")))?;
+                match c {
+                    Some(c) => parent.text.push(c),
+                    None => {
+                        let name = entity.decode().map_err(decode_err)?;
+                        let value = resolve_predefined_entity(&name)
+                            .ok_or_else(|| Error::Xml(src!("Unknown XML entity '&{name};'")))?;
+                        parent.text.push_str(value);
+                    }
+                }
+            }
+            Event::End(_) => {
+                let mut element = open.pop().expect("The matched element is open");
+                element.text = element.text.trim().to_string();
+                match open.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            Event::Eof => {
+                return Err(Error::Xml(src!("XML ended inside tag '{}'", open[0].name)));
+            }
+            _ => (),
         }
     }
 }
 
+/*
+Which attributes of an element to read, and how.
+*/
+#[derive(Clone, Debug)]
+pub(crate) struct AttributeSpec {
+    // The attributes to read, all of them when `None`.
+    wanted: Option<Vec<String>>,
+    // Whether to unescape values, which `FieldIter` has never done.
+    unescape: bool,
+}
+
+impl AttributeSpec {
+    pub(crate) const ALL: AttributeSpec = AttributeSpec { wanted: None, unescape: true };
+}
+
+/*
+The attributes of an element as `spec` asks. Malformed attributes are an error only when
+all are wanted, as they cannot be told apart.
+*/
+pub(crate) fn read_attributes(
+    bytes_start: &BytesStart,
+    decoder: Decoder,
+    spec: &AttributeSpec) -> Result<Attributes>
+{
+    let wanted = spec.wanted.as_deref();
+    let mut attributes = Vec::new();
+    for attr in bytes_start.attributes() {
+        let attr = match (attr, wanted) {
+            (Ok(attr), _) => attr,
+            (Err(e), None) => return Err(Error::Xml(src!("XML attribute error: {e}"))),
+            (Err(_), Some(_)) => continue,
+        };
+        let key = attr.key.as_ref();
+        if wanted.is_some_and(|wanted| !wanted.iter().any(|name| name.as_bytes() == key)) {
+            continue;
+        }
+        let name = decoder
+            .decode(key)
+            .map_err(|e| Error::Xml(src!("XML decoding error for attribute: {e}")))?;
+        let value = match spec.unescape {
+            true => attr.decode_and_unescape_value(decoder),
+            false => decoder.decode(&attr.value).map_err(Into::into),
+        }
+        .map_err(|e| Error::Xml(src!("XML decoding error for attribute '{}': {}", name, e)))?;
+        attributes.push((name.to_string(), value.to_string()));
+    }
+    Ok(Attributes(attributes))
+}
+
+/**
+A [`RequestSpec`] without its API key, to store or send elsewhere. With the ``serde``
+feature it is serializable, and so is a ``RequestSpec`` in this form.
+```
+use fred_api::{build_request, StoredRequest};
+
+let req = build_request("series?series_id=GNPCA&", Some("abcd")).unwrap();
+let stored = StoredRequest::from(&req);
+assert_eq!(stored.mid_part, "series?series_id=GNPCA&");
+let req = stored.into_request(Some("efgh")).unwrap();
+assert!(req.uri().unwrap().to_string().ends_with("api_key=efgh"));
+```
+*/
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct StoredRequest {
+    pub mid_part: String,
+}
+
+impl StoredRequest {
+
+    /**
+    If ``api_key`` is ``None``, checks for environment variable ``FRED_API_KEY``.
+    */
+    pub fn into_request(self, api_key: Option<&str>) -> Result<RequestSpec> {
+        RequestSpec::new(&self.mid_part, api_key)
+    }
+}
+
+impl From<&RequestSpec> for StoredRequest {
+    fn from(req: &RequestSpec) -> Self { StoredRequest { mid_part: req.mid_part() } }
+}
+
 /**
 Determines the lookup method. A successful request will always write to the cache.
+Entries cached without metadata have an unknown age and are always stale.
 */
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Lookup {
     FredOnCacheMiss,
     FredOnly,
     CacheOnly,
+    /// Goes to FRED when the cached entry is missing or at least this old.
+    FredIfOlderThan(Duration),
+    /// Goes to FRED when the cached entry is missing or older than its endpoint's TTL,
+    /// see [`TtlPolicy`].
+    FredIfStale,
+    /// Returns a cached entry however old, refreshing it in the background when it is
+    /// older than its endpoint's TTL. Goes to FRED on a cache miss.
+    StaleWhileRevalidate,
 }
 
+serde_via_str!(Lookup);
+
+/**
+Spelt as [`FromStr`] reads it. ``FredIfOlderThan`` is written in whole seconds.
+*/
+impl fmt::Display for Lookup {
+    // test: lookup_from_str_works
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lookup::FredOnCacheMiss => f.write_str("fred_on_cache_miss"),
+            Lookup::FredOnly => f.write_str("fred_only"),
+            Lookup::CacheOnly => f.write_str("cache_only"),
+            Lookup::FredIfOlderThan(age) => write!(f, "fred_if_older_than:{}", age.as_secs()),
+            Lookup::FredIfStale => f.write_str("fred_if_stale"),
+            Lookup::StaleWhileRevalidate => f.write_str("stale_while_revalidate"),
+        }
+    }
+}
+
+/**
+``FredIfOlderThan`` is spelt with its age in seconds, such as ``fred_if_older_than:3600``.
+*/
 impl FromStr for Lookup {
-    type Err = DebugErr;
+    type Err = Error;
     
+    // test: lookup_from_str_works
     fn from_str(s: &str) -> Result<Self> {
         match s {
             "fred_on_cache_miss" => Ok(Lookup::FredOnCacheMiss),
             "fred_only" => Ok(Lookup::FredOnly),
             "cache_only" => Ok(Lookup::CacheOnly),
-            _ => Err(src!("Could not parse '{}'", s))?,
+            "fred_if_stale" => Ok(Lookup::FredIfStale),
+            "stale_while_revalidate" => Ok(Lookup::StaleWhileRevalidate),
+            _ => match s.strip_prefix("fred_if_older_than:").map(|secs| secs.parse::<u64>()) {
+                Some(Ok(secs)) => Ok(Lookup::FredIfOlderThan(Duration::from_secs(secs))),
+                _ => Err(Error::Config(src!("Could not parse '{}'", s)))?,
+            },
         }
     }
 }
@@ -394,6 +981,7 @@ mod test {
     use {
         crate::*,
         lazy_static::lazy_static,
+        sled::Db,
         tempfile::TempDir,
     };
 
@@ -478,7 +1066,18 @@ mod test {
             .unwrap()
             .uri()
             .is_ok();
-        assert_eq!(e.msg, "invalid uri character");
+        assert_eq!(e.msg(), "invalid uri character");
+    }
+
+    #[test]
+    fn uri_with_base_works() {
+        let req = RequestSpec::new("tags?", Some("abcd")).unwrap();
+        for base in ["http://127.0.0.1:8080/fred", "http://127.0.0.1:8080/fred/"] {
+            assert_eq!(
+                req.uri_with_base(base).unwrap().to_string(),
+                "http://127.0.0.1:8080/fred/tags?api_key=abcd",
+            );
+        }
     }
 
     #[test]
@@ -489,6 +1088,49 @@ mod test {
         assert_eq!(b"observations?series_id=GNPCA&", &*ivec);
     }
 
+    #[test]
+    fn canonical_key_ignores_parameter_order() {
+        let a = RequestSpec::new("series/observations?series_id=GNPCA&units=pch&", Some("abcd"))
+            .unwrap();
+        let b = RequestSpec::new("/series/observations?units=pch&series_id=GNPCA", Some("efgh"))
+            .unwrap();
+        assert_eq!(a.ivec(), b.ivec());
+        assert_eq!(a.canonical(), "series/observations?series_id=GNPCA&units=pch&");
+
+        // Percent encoding, api_key and the default file type are folded in.
+        assert_eq!(
+            canonical_mid_part("series/search?search_text=money+stock&file_type=xml&api_key=x&"),
+            canonical_mid_part("series/search?search_text=money%20stock&"),
+        );
+        assert_ne!(
+            canonical_mid_part("series?series_id=GNPCA&file_type=json&"),
+            canonical_mid_part("series?series_id=GNPCA&"),
+        );
+        assert_eq!(canonical_mid_part("tags?"), "tags?");
+        assert_eq!(canonical_mid_part("tags"), "tags?");
+
+        let format = |mid_part| RequestSpec::new(mid_part, Some("abcd")).unwrap().format();
+        assert_eq!(format("series?file_type=json&series_id=GNPCA&"), Format::Json);
+        assert_eq!(format("series?series_id=GNPCA&file_type=xml&"), Format::Xml);
+        assert_eq!(format("series?series_id=file_type=json&"), Format::Xml);
+    }
+
+    #[test]
+    fn migrate_cache_keys_works() {
+        let db = create_temp_cache();
+        db.insert("series?units=pch&series_id=GNPCA&", "old").unwrap();
+        db.insert("series?series_id=UNRATE&units=pch&", "canonical").unwrap();
+        db.insert("series?units=pch&series_id=UNRATE&", "duplicate").unwrap();
+
+        assert_eq!(migrate_cache_keys(&db).unwrap(), 2);
+        assert_eq!(db.len(), 2);
+        assert_eq!(db.get("series?series_id=GNPCA&units=pch&").unwrap().unwrap(), "old");
+        assert_eq!(db.get("series?series_id=UNRATE&units=pch&").unwrap().unwrap(), "canonical");
+
+        // Already canonical.
+        assert_eq!(migrate_cache_keys(&db).unwrap(), 0);
+    }
+
     #[test]
     fn bytes_from_ivec_are_unchanged() {
         let bytes: Vec<u8> = r#"<?xml version=\"1.0\" encoding=\"utf-8\" ?>\n<observations realtime_start=\"2025-10-04\" realtime_end=\"2025-10-04\" observation_start=\"1600-01-01\" observation_end=\"9999-12-31\" units=\"lin\" output_type=\"1\" file_type=\"xml\" order_by=\"observation_date\" sort_order=\"asc\" count=\"210\" offset=\"0\" limit=\"100000\">\n  <observation realtime_start=\"2025-10-04\" realtime_end=\"2025-10-04\" date=\"1971-04-01\" value=\"0.850603488248666\"/>\n  <observation realtime_start=\"2025-10-04\" realtime_end=\"2025-10-04\" date=\"1971-07-01\" value=\"3.43557210303712\"/>\n  <observation realtime_start=\"2025-10-04\" realtime_end=\"2025-10-04\" date=\"1971-10-01\" value=\"1.90453329926268\"/>\n  <observation realtime_start=\"2025-10-04\" realtime_end=\"2025-10-04\" date=\"1972-01-01\" value=\"0.988357368475625\"/>\n  <observation realtime_start=\"2025-10-04\" realtime_end=\"2025-10-04\" date=\"1972-04-01\" value=\"1.14890574736089\"/>\n  <observation realtime_start=\"2025-10-04\" realtime_end=\"2025-10-04\" date=\"1972-07-01\" value=\"1.84453195549092\"/>\n  <observation realtime_start=\"2025-10-04\" realtime_end=\"2025-10-04\" date=\"1972-10-01\" value=\"0.838712265813754\"/>\n</observations>\n\n\n\n"#.into();
@@ -511,6 +1153,49 @@ mod test {
         );
     }
 
+    #[test]
+    fn lookup_from_str_works() {
+        assert_eq!(Lookup::from_str("cache_only").unwrap(), Lookup::CacheOnly);
+        assert_eq!(
+            Lookup::from_str("fred_if_older_than:3600").unwrap(),
+            Lookup::FredIfOlderThan(Duration::from_secs(3600)),
+        );
+        assert_eq!(
+            Lookup::from_str("stale_while_revalidate").unwrap(),
+            Lookup::StaleWhileRevalidate,
+        );
+        assert!(Lookup::from_str("fred_if_older_than:1h").is_err());
+        assert!(Lookup::from_str("never").is_err());
+
+        for lookup in [
+            Lookup::FredOnCacheMiss,
+            Lookup::FredOnly,
+            Lookup::CacheOnly,
+            Lookup::FredIfOlderThan(Duration::from_secs(60)),
+            Lookup::FredIfStale,
+            Lookup::StaleWhileRevalidate,
+        ] {
+            assert_eq!(Lookup::from_str(&lookup.to_string()).unwrap(), lookup);
+        }
+    }
+
+    #[test]
+    fn serde_uses_text_forms() {
+        let lookup = Lookup::FredIfOlderThan(Duration::from_secs(3600));
+        let json = serde_json::to_string(&lookup).unwrap();
+        assert_eq!(json, r#""fred_if_older_than:3600""#);
+        assert_eq!(serde_json::from_str::<Lookup>(&json).unwrap(), lookup);
+        assert_eq!(serde_json::from_str::<Lookup>(r#""fred_on_cache_miss""#).unwrap(), Lookup::FredOnCacheMiss);
+        let e = serde_json::from_str::<Lookup>(r#""never""#).unwrap_err();
+        assert!(e.to_string().contains("Could not parse 'never'"), "{e}");
+
+        let req = RequestSpec::new("series?series_id=GNPCA&", Some("secret")).unwrap();
+        let json = serde_json::to_string(&req).unwrap();
+        assert_eq!(json, r#"{"mid_part":"series?series_id=GNPCA&"}"#);
+        let stored: StoredRequest = serde_json::from_str(&json).unwrap();
+        assert_eq!(stored.into_request(Some("abcd")).unwrap().canonical(), req.canonical());
+    }
+
     #[test]
     fn has_api_key_works() {
         let req = RequestSpec::new("observations?series_id=GNPCA&", Some("abcd")).unwrap();
@@ -539,9 +1224,9 @@ mod test {
         );
 
         if let Err(e) = field_iter.next().unwrap() {
-            assert!(e.msg.contains("invalid utf-8"))
+            assert!(e.msg().contains("invalid utf-8"))
         } else {
-            assert!(false, "Should fail")
+            panic!("Should fail")
         }
 
         assert!(field_iter.next().is_none())
@@ -563,5 +1248,114 @@ mod test {
             vec!["1929-01-01".to_string(), "1202.659".to_string()],
         );
     }
-}
 
+    #[test]
+    fn optional_field_iter_works() {
+        let xml = r#"<releases>
+        <release id="9" name="Retail &amp; Food" press_release="true" link="http://www.census.gov/retail/"/>
+        <release id="10" name="Consumer Price Index" press_release="" />
+        <release name="No id" />
+    </releases>"#;
+        let fields = vec!["id", "name", "press_release?", "link?"];
+        let mut iter = OptionalFieldIter::new("release", fields, xml.into());
+        let row = |values: [Option<&str>; 4]| values.map(|v| v.map(str::to_string)).to_vec();
+
+        assert_eq!(iter.next().unwrap().unwrap(), row([
+            Some("9"),
+            Some("Retail & Food"),
+            Some("true"),
+            Some("http://www.census.gov/retail/"),
+        ]));
+        assert_eq!(iter.next().unwrap().unwrap(), row([Some("10"), Some("Consumer Price Index"), None, None]));
+        assert!(matches!(iter.next(), Some(Err(Error::MissingAttribute { .. }))));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn attribute_iter_keeps_order() {
+        let xml = r#"<tags>
+        <tag name="nation" group_id="geot" notes="" popularity="100"/>
+        <tag name="usa" group_id="geo"></tag>
+    </tags>"#;
+        let rows: Vec<Attributes> = AttributeIter::new("tag", xml.into())
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].names().collect::<Vec<_>>(), ["name", "group_id", "notes", "popularity"]);
+        assert_eq!((rows[0].get("notes"), rows[0].get("missing")), (Some(""), None));
+        assert_eq!(rows[1].iter().collect::<Vec<_>>(), [("name", "usa"), ("group_id", "geo")]);
+        assert_eq!(
+            rows[1].clone().into_iter().next(),
+            Some(("name".to_string(), "usa".to_string())),
+        );
+    }
+
+    #[test]
+    fn lenient_field_iter_continues_past_bad_rows() {
+        let xml = r#"<observations>
+        <observation date="1929-01-01" value="1202.659" />
+        <observation date="1929-02-01" value="" />
+        <observation date="1930-01-01" value="1000.0" />
+    </observations>"#;
+        let rows: Vec<_> = FieldIter::new("observation", vec!["date", "value"], xml.into())
+            .lenient()
+            .collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].as_ref().unwrap()[1], "1202.659");
+        assert!(rows[1].as_ref().unwrap_err().msg().starts_with("Empty attribute 'value'"));
+        assert_eq!(rows[2].as_ref().unwrap()[0], "1930-01-01");
+
+        // Without lenient the empty value ends iteration.
+        let strict = FieldIter::new("observation", vec!["date", "value"], xml.into());
+        assert_eq!(strict.count(), 2);
+    }
+
+    #[test]
+    fn field_iter_reads_requested_attributes_as_written() {
+        let xml = r#"<series id="SP500" title="S&amp;P 500" notes="Copyright &copy; 2016"/>"#;
+        let row = FieldIter::new("series", vec!["id", "title", "notes"], xml.into()).next();
+        assert_eq!(row.unwrap().unwrap(), ["SP500", "S&amp;P 500", "Copyright &copy; 2016"]);
+
+        // The other iterators unescape, and the unknown entity in notes fails only a row
+        // that reads notes.
+        let row = OptionalFieldIter::new("series", vec!["title"], xml.into()).next();
+        assert_eq!(row.unwrap().unwrap(), [Some("S&P 500".to_string())]);
+        let row = OptionalFieldIter::new("series", vec!["notes?"], xml.into()).next();
+        assert!(matches!(row, Some(Err(Error::Xml(_)))));
+        assert!(AttributeIter::new("series", xml.into()).next().unwrap().is_err());
+    }
+
+    #[test]
+    fn field_iter_selects_child_elements() {
+        let xml = r#"<release_tables>
+        <element element_id="12886" release_id="50" name="Total nonfarm">
+            <notes>Jobs &amp; hours &#x2014;<![CDATA[ <b>raw</b>]]></notes>
+            <children>
+                <element element_id="12887" name="Total private"><line>2</line></element>
+            </children>
+        </element>
+        <element element_id="12900" release_id="50" name="Government"/>
+    </release_tables>"#;
+        let fields = vec!["@name", "./notes", "children/element@name", "children/element/line"];
+        let mut iter = FieldIter::new("element", fields, xml.into());
+        assert_eq!(
+            iter.next().unwrap().unwrap(),
+            vec!["Total nonfarm", "Jobs & hours \u{2014} <b>raw</b>", "Total private", "2"],
+        );
+        // The nested element was read as part of the first.
+        match iter.next().unwrap() {
+            Err(Error::MissingAttribute { attribute, .. }) => assert_eq!(attribute, "./notes"),
+            _ => panic!("Should miss notes"),
+        }
+
+        let rows: Vec<_> = OptionalFieldIter::new("element", vec!["name", "./notes?"], xml.into())
+            .map(|row| row.unwrap())
+            .collect();
+        assert_eq!(rows[1], vec![Some("Government".to_string()), None]);
+
+        let xml = "<seriess><series><id>GNPCA</id></series><series><id></id></series></seriess>";
+        let mut iter = FieldIter::new("id", vec!["."], xml.into());
+        assert_eq!(iter.next().unwrap().unwrap(), vec!["GNPCA"]);
+        assert!(iter.next().unwrap().unwrap_err().msg().starts_with("Empty attribute '.'"));
+    }
+}
@@ -0,0 +1,337 @@
+/*!
+A bounded in-memory tier in front of another [`CacheStore`].
+*/
+
+use {
+    crate::{CacheEntry, CacheStore, Result},
+    sled::IVec,
+    std::{
+        collections::{BTreeMap, HashMap},
+        sync::{Arc, Mutex},
+    },
+};
+
+/**
+Counts kept by an [`LruStore`] since it was made.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LruStats {
+    /// Reads answered from memory.
+    pub hits: u64,
+    /// Reads passed on to the inner store.
+    pub misses: u64,
+    /// Entries held now.
+    pub entries: usize,
+    /// Bytes of the bodies held now.
+    pub bytes: usize,
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    // Each entry with the tick of its last use.
+    entries: HashMap<IVec, (CacheEntry, u64)>,
+    // Keys by the tick of their last use, oldest first.
+    order: BTreeMap<u64, IVec>,
+    tick: u64,
+    // Writes and removals so far, and those under way by key, see `LruStore::get_entry`.
+    writes: u64,
+    writing: HashMap<IVec, u32>,
+    stats: LruStats,
+}
+
+impl Lru {
+    fn get(&mut self, key: &[u8]) -> Option<CacheEntry> {
+        self.tick += 1;
+        let (entry, used) = self.entries.get_mut(key)?;
+        let key = self.order.remove(used)?;
+        *used = self.tick;
+        self.order.insert(self.tick, key);
+        Some(entry.clone())
+    }
+
+    fn insert(&mut self, key: &[u8], entry: CacheEntry, max_entries: usize, max_bytes: usize) {
+        self.forget(key);
+        let len = entry.body.len();
+        if max_entries == 0 || len > max_bytes {
+            return;
+        }
+        self.tick += 1;
+        self.entries.insert(key.into(), (entry, self.tick));
+        self.order.insert(self.tick, key.into());
+        self.stats.bytes += len;
+        while self.entries.len() > max_entries || self.stats.bytes > max_bytes {
+            let Some((_, oldest)) = self.order.pop_first() else { break };
+            if let Some((entry, _)) = self.entries.remove(&oldest) {
+                self.stats.bytes -= entry.body.len();
+            }
+        }
+        self.stats.entries = self.entries.len();
+    }
+
+    // Before a write or removal of `key`, returning the writes so far.
+    fn start_write(&mut self, key: &[u8]) -> u64 {
+        self.writes += 1;
+        self.forget(key);
+        *self.writing.entry(key.into()).or_default() += 1;
+        self.writes
+    }
+
+    // After a write or removal of `key`, returning whether no other ran meanwhile.
+    fn end_write(&mut self, key: &[u8], writes: u64) -> bool {
+        let alone = self.writing.get(key) == Some(&1) && self.writes == writes;
+        let n = self.writing.get_mut(key).expect("The write was started");
+        *n -= 1;
+        if *n == 0 {
+            self.writing.remove(key);
+        }
+        alone
+    }
+
+    fn forget(&mut self, key: &[u8]) {
+        if let Some((entry, used)) = self.entries.remove(key) {
+            self.order.remove(&used);
+            self.stats.bytes -= entry.body.len();
+            self.stats.entries = self.entries.len();
+        }
+    }
+}
+
+/**
+Keeps the most recently used entries of ``inner`` in memory, up to
+[`max_entries`](Self::with_max_entries) entries and
+[`max_bytes`](Self::with_max_bytes) bytes of bodies. Writes go to ``inner`` and then
+replace the entry in memory, so a refresh is seen at once by every clone. Concurrent
+writes of one key leave it to be read again from ``inner``. Writes to ``inner`` made
+other than through this store, such as by another process, are not seen.
+```
+use fred_api::{build_request, cache_request, FredClient, LruStore};
+
+let dir = tempfile::TempDir::new().unwrap();
+let store = LruStore::new(sled::open(dir.path()).unwrap()).with_max_bytes(16 << 20);
+let client = FredClient::new(store.clone(), Some("abcd")).unwrap();
+let req = client.request("series?series_id=CPIAUCSL&").unwrap();
+assert!(cache_request(&req, &store).unwrap().is_none());
+assert_eq!(store.stats().misses, 1);
+```
+*/
+#[derive(Clone, Debug)]
+pub struct LruStore<S> {
+    inner: S,
+    lru: Arc<Mutex<Lru>>,
+    max_entries: usize,
+    max_bytes: usize,
+}
+
+impl<S: CacheStore> LruStore<S> {
+
+    /**
+    At most 1,000 entries and 64 MiB of bodies in front of ``inner``.
+    */
+    pub fn new(inner: S) -> Self {
+        LruStore {
+            inner,
+            lru: Arc::new(Mutex::new(Lru::default())),
+            max_entries: 1_000,
+            max_bytes: 64 << 20,
+        }
+    }
+
+    /**
+    Keep at most ``max_entries`` entries in memory, none with ``0``.
+    */
+    // test: lru_store_evicts_least_recently_used
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /**
+    Keep at most ``max_bytes`` bytes of bodies in memory. Larger bodies are never kept.
+    */
+    // test: lru_store_evicts_least_recently_used
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    pub fn inner(&self) -> &S { &self.inner }
+
+    // test: lru_store_evicts_least_recently_used
+    pub fn stats(&self) -> LruStats { self.lru.lock().unwrap().stats }
+
+    /**
+    Drop every entry held in memory, keeping the counts.
+    */
+    pub fn clear(&self) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.entries.clear();
+        lru.order.clear();
+        lru.stats.entries = 0;
+        lru.stats.bytes = 0;
+    }
+}
+
+impl<S: CacheStore> CacheStore for LruStore<S> {
+    fn get_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        let writes = {
+            let mut lru = self.lru.lock().unwrap();
+            if let Some(entry) = lru.get(key) {
+                lru.stats.hits += 1;
+                return Ok(Some(entry));
+            }
+            lru.stats.misses += 1;
+            lru.writes
+        };
+        let entry = self.inner.get_entry(key)?;
+        let mut lru = self.lru.lock().unwrap();
+        // A write while reading may have made the entry read out of date.
+        let current = lru.writes == writes && !lru.writing.contains_key(key);
+        if let (Some(entry), true) = (&entry, current) {
+            lru.insert(key, entry.clone(), self.max_entries, self.max_bytes);
+        }
+        Ok(entry)
+    }
+
+    fn put_entry(&self, key: &[u8], entry: &CacheEntry, keep_previous: bool) -> Result<()> {
+        // Forgotten first, so a failed write cannot leave the old value in memory.
+        let writes = self.lru.lock().unwrap().start_write(key);
+        let result = self.inner.put_entry(key, entry, keep_previous);
+        let mut lru = self.lru.lock().unwrap();
+        // With other writes of the key at the same time, any of them may have reached
+        // `inner` last, so the entry is left for the next read.
+        if lru.end_write(key, writes) && result.is_ok() {
+            lru.insert(key, entry.clone(), self.max_entries, self.max_bytes);
+        }
+        result
+    }
+
+    fn get_previous(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        self.inner.get_previous(key)
+    }
+
+    fn remove_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        let writes = self.lru.lock().unwrap().start_write(key);
+        let result = self.inner.remove_entry(key);
+        self.lru.lock().unwrap().end_write(key, writes);
+        result
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<(IVec, CacheEntry)>> + '_> {
+        self.inner.entries()
+    }
+
+    fn flush_entries(&self) -> Result<()> {
+        self.inner.flush_entries()
+    }
+
+    fn store_id(&self) -> usize { Arc::as_ptr(&self.lru) as usize }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::*,
+        sled::IVec,
+        std::{
+            sync::{Arc, Barrier},
+            thread,
+        },
+    };
+
+    fn entry(body: &str) -> CacheEntry {
+        CacheEntry { body: body.as_bytes().into(), meta: None }
+    }
+
+    // Holds a write of "slow" after it reaches the store, until two waits on `gate`.
+    #[derive(Clone)]
+    struct Gated {
+        store: MemoryStore,
+        gate: Arc<Barrier>,
+    }
+
+    impl CacheStore for Gated {
+        fn get_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+            self.store.get_entry(key)
+        }
+
+        fn put_entry(&self, key: &[u8], entry: &CacheEntry, keep_previous: bool) -> Result<()> {
+            self.store.put_entry(key, entry, keep_previous)?;
+            if entry.body == "slow" {
+                self.gate.wait();
+                self.gate.wait();
+            }
+            Ok(())
+        }
+
+        fn get_previous(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+            self.store.get_previous(key)
+        }
+
+        fn remove_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+            self.store.remove_entry(key)
+        }
+
+        fn entries(&self) -> Box<dyn Iterator<Item = Result<(IVec, CacheEntry)>> + '_> {
+            self.store.entries()
+        }
+    }
+
+    #[test]
+    fn lru_store_keeps_up_with_concurrent_writes() {
+        let inner = Gated { store: MemoryStore::new(), gate: Arc::new(Barrier::new(2)) };
+        let store = LruStore::new(inner.clone());
+        let slow = {
+            let store = store.clone();
+            thread::spawn(move || store.put_entry(b"a", &entry("slow"), false).unwrap())
+        };
+        // "fast" reaches the inner store after "slow" but finishes first.
+        inner.gate.wait();
+        store.put_entry(b"a", &entry("fast"), false).unwrap();
+        inner.gate.wait();
+        slow.join().unwrap();
+        assert_eq!(inner.store.get_entry(b"a").unwrap(), Some(entry("fast")));
+        assert_eq!(store.get_entry(b"a").unwrap(), Some(entry("fast")));
+
+        // Writes one at a time are kept in memory.
+        store.put_entry(b"a", &entry("last"), false).unwrap();
+        assert_eq!(store.get_entry(b"a").unwrap(), Some(entry("last")));
+        assert_eq!(store.stats().hits, 1);
+    }
+
+    #[test]
+    fn lru_store_evicts_least_recently_used() {
+        let inner = MemoryStore::new();
+        for key in ["a", "b", "c"] {
+            inner.put_entry(key.as_bytes(), &entry(key), false).unwrap();
+        }
+        let store = LruStore::new(inner.clone()).with_max_entries(2);
+        for key in ["a", "b", "a", "c", "a"] {
+            assert_eq!(store.get_entry(key.as_bytes()).unwrap(), Some(entry(key)));
+        }
+        // "b" was used least recently when "c" came in.
+        let stats = store.stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries, stats.bytes), (2, 3, 2, 2));
+        store.get_entry(b"b").unwrap();
+        assert_eq!(store.stats().misses, 4);
+        assert_eq!(store.get_entry(b"missing").unwrap(), None);
+        assert_eq!(store.stats().misses, 5);
+
+        // A refresh through the store replaces the value in memory.
+        store.put_entry(b"b", &entry("b2"), true).unwrap();
+        assert_eq!(store.get_entry(b"b").unwrap(), Some(entry("b2")));
+        assert_eq!(inner.get_previous(b"b").unwrap(), Some(entry("b")));
+        assert_eq!(store.stats().hits, 3);
+        store.remove_entry(b"b").unwrap();
+        assert_eq!(store.get_entry(b"b").unwrap(), None);
+
+        // Bodies over the byte limit are read through without being kept.
+        let store = LruStore::new(inner).with_max_bytes(1);
+        store.put_entry(b"big", &entry("too big"), false).unwrap();
+        store.get_entry(b"big").unwrap();
+        store.get_entry(b"a").unwrap();
+        store.get_entry(b"a").unwrap();
+        assert_eq!(store.stats(), LruStats { hits: 1, misses: 2, entries: 1, bytes: 1 });
+        store.clear();
+        assert_eq!((store.stats().entries, store.stats().bytes), (0, 0));
+    }
+}
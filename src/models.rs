@@ -0,0 +1,630 @@
+/*!
+Typed records parsed from FRED responses, serializable with the ``serde`` feature. Each
+parser reads every element of its tag, so ``series`` reads the ``<series>`` of
+``series``, ``series/search`` or ``category/series`` alike. Responses requested with
+``file_type=json`` are read the same way with the ``json`` feature.
+*/
+
+use {
+    crate::{
+        endpoints::{OrderBy, OutputType, SortOrder, Units},
+        format::{json_rows, root_attributes},
+        src, AttributeIter, Attributes, DebugErr, Error, Format, PageInfo, Result,
+    },
+    sled::IVec,
+    std::{fmt, str::FromStr},
+};
+
+/**
+A calendar date as FRED writes it, ``YYYY-MM-DD``. FRED marks an open-ended real-time
+period with [`Date::OPEN_END`], ``9999-12-31``.
+```
+use fred_api::models::Date;
+
+let date: Date = "2024-02-29".parse().unwrap();
+assert_eq!((date.year(), date.month(), date.day()), (2024, 2, 29));
+assert!("2023-02-29".parse::<Date>().is_err());
+assert!("9999-12-31".parse::<Date>().unwrap().is_open_end());
+```
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    year: u16,
+    month: u8,
+    day: u8,
+}
+
+impl Date {
+
+    /**
+    The ``9999-12-31`` sentinel FRED uses for "until further notice".
+    */
+    pub const OPEN_END: Date = Date { year: 9999, month: 12, day: 31 };
+
+    /**
+    ``None`` unless the date exists in the Gregorian calendar.
+    */
+    // test: date_parses_and_orders
+    pub fn new(year: u16, month: u8, day: u8) -> Option<Self> {
+        let leap = year.is_multiple_of(4)
+            && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+        let days = match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+            4 | 6 | 9 | 11 => 30,
+            2 if leap => 29,
+            2 => 28,
+            _ => return None,
+        };
+        (year > 0 && (1..=days).contains(&day)).then_some(Date { year, month, day })
+    }
+
+    pub fn year(&self) -> u16 { self.year }
+
+    pub fn month(&self) -> u8 { self.month }
+
+    pub fn day(&self) -> u8 { self.day }
+
+    pub fn is_open_end(&self) -> bool { *self == Date::OPEN_END }
+}
+
+impl FromStr for Date {
+    type Err = Error;
+
+    // test: date_parses_and_orders
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.splitn(3, '-');
+        let mut next = || parts.next().filter(|p| p.bytes().all(|b| b.is_ascii_digit()));
+        let date = match (next(), next(), next()) {
+            (Some(y), Some(m), Some(d)) if y.len() == 4 && m.len() == 2 && d.len() == 2 => {
+                Date::new(y.parse().unwrap(), m.parse().unwrap(), d.parse().unwrap())
+            },
+            _ => None,
+        };
+        date.ok_or(Error::Config(src!("Could not parse date '{s}'")))
+    }
+}
+
+serde_via_str!(Date);
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/**
+One ``<observation>`` of ``series/observations``. FRED writes ``"."`` for a missing
+value, which is ``None`` here.
+*/
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct Observation {
+    pub date: Date,
+    pub value: Option<f64>,
+    pub realtime_start: Date,
+    /// [`Date::OPEN_END`] while the value is current.
+    pub realtime_end: Date,
+}
+
+/**
+Parse the observations of a ``series/observations`` response.
+```
+use fred_api::models::{observations, Date};
+
+let xml = r#"<observations>
+    <observation realtime_start="2024-01-01" realtime_end="9999-12-31" date="2020-01-01" value="1.5"/>
+    <observation realtime_start="2024-01-01" realtime_end="9999-12-31" date="2020-02-01" value="."/>
+</observations>"#;
+let obs = observations(xml.as_bytes().into()).unwrap();
+assert_eq!(obs[0].value, Some(1.5));
+assert_eq!(obs[1].value, None);
+assert_eq!(obs[1].date, "2020-02-01".parse::<Date>().unwrap());
+assert!(obs[1].realtime_end.is_open_end());
+```
+*/
+// test: observations_parses_missing_values
+pub fn observations(bytes: IVec) -> Result<Vec<Observation>> { parse_all(&bytes) }
+
+impl FromRow for Observation {
+    const TAG: &'static str = "observation";
+    const JSON_KEY: &'static str = "observations";
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Observation {
+            date: row.parse("date")?,
+            value: match row.required("value")? {
+                "." => None,
+                _ => Some(row.parse("value")?),
+            },
+            realtime_start: row.parse("realtime_start")?,
+            realtime_end: row.parse("realtime_end")?,
+        })
+    }
+}
+
+/**
+One ``<series>`` of ``series``, ``series/search``, ``category/series`` and the like.
+*/
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct Series {
+    pub id: String,
+    pub realtime_start: Date,
+    pub realtime_end: Date,
+    pub title: String,
+    pub observation_start: Date,
+    pub observation_end: Date,
+    /// Such as ``Annual``, with ``frequency_short`` ``A``.
+    pub frequency: String,
+    pub frequency_short: String,
+    pub units: String,
+    pub units_short: String,
+    pub seasonal_adjustment: String,
+    pub seasonal_adjustment_short: String,
+    /// As FRED writes it, such as ``2013-07-31 09:26:16-05``.
+    pub last_updated: String,
+    pub popularity: u32,
+    pub group_popularity: Option<u32>,
+    pub notes: Option<String>,
+}
+
+// test: metadata_models_parse_fred_samples
+pub fn series(bytes: IVec) -> Result<Vec<Series>> { parse_all(&bytes) }
+
+impl FromRow for Series {
+    const TAG: &'static str = "series";
+    const JSON_KEY: &'static str = "seriess";
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Series {
+            id: row.string("id")?,
+            realtime_start: row.parse("realtime_start")?,
+            realtime_end: row.parse("realtime_end")?,
+            title: row.string("title")?,
+            observation_start: row.parse("observation_start")?,
+            observation_end: row.parse("observation_end")?,
+            frequency: row.string("frequency")?,
+            frequency_short: row.string("frequency_short")?,
+            units: row.string("units")?,
+            units_short: row.string("units_short")?,
+            seasonal_adjustment: row.string("seasonal_adjustment")?,
+            seasonal_adjustment_short: row.string("seasonal_adjustment_short")?,
+            last_updated: row.string("last_updated")?,
+            popularity: row.parse("popularity")?,
+            group_popularity: row.parse_optional("group_popularity")?,
+            notes: row.optional("notes"),
+        })
+    }
+}
+
+/**
+One ``<category>`` of ``category``, ``category/children`` and the like.
+*/
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct Category {
+    pub id: u32,
+    pub name: String,
+    /// ``0`` for the root category's children.
+    pub parent_id: u32,
+    pub notes: Option<String>,
+}
+
+// test: metadata_models_parse_fred_samples
+pub fn categories(bytes: IVec) -> Result<Vec<Category>> { parse_all(&bytes) }
+
+impl FromRow for Category {
+    const TAG: &'static str = "category";
+    const JSON_KEY: &'static str = "categories";
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Category {
+            id: row.parse("id")?,
+            name: row.string("name")?,
+            parent_id: row.parse("parent_id")?,
+            notes: row.optional("notes"),
+        })
+    }
+}
+
+/**
+One ``<release>`` of ``release``, ``releases``, ``series/release`` and the like.
+*/
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct Release {
+    pub id: u32,
+    pub realtime_start: Date,
+    pub realtime_end: Date,
+    pub name: String,
+    /// ``None`` where FRED leaves out ``press_release``, as for some older releases.
+    pub press_release: Option<bool>,
+    pub link: Option<String>,
+    pub notes: Option<String>,
+}
+
+// test: metadata_models_parse_fred_samples
+pub fn releases(bytes: IVec) -> Result<Vec<Release>> { parse_all(&bytes) }
+
+impl FromRow for Release {
+    const TAG: &'static str = "release";
+    const JSON_KEY: &'static str = "releases";
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Release {
+            id: row.parse("id")?,
+            realtime_start: row.parse("realtime_start")?,
+            realtime_end: row.parse("realtime_end")?,
+            name: row.string("name")?,
+            press_release: row.parse_optional("press_release")?,
+            link: row.optional("link"),
+            notes: row.optional("notes"),
+        })
+    }
+}
+
+/**
+One ``<source>`` of ``source``, ``sources`` and ``release/sources``.
+*/
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct Source {
+    pub id: u32,
+    pub realtime_start: Date,
+    pub realtime_end: Date,
+    pub name: String,
+    pub link: Option<String>,
+    pub notes: Option<String>,
+}
+
+// test: metadata_models_parse_fred_samples
+pub fn sources(bytes: IVec) -> Result<Vec<Source>> { parse_all(&bytes) }
+
+impl FromRow for Source {
+    const TAG: &'static str = "source";
+    const JSON_KEY: &'static str = "sources";
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Source {
+            id: row.parse("id")?,
+            realtime_start: row.parse("realtime_start")?,
+            realtime_end: row.parse("realtime_end")?,
+            name: row.string("name")?,
+            link: row.optional("link"),
+            notes: row.optional("notes"),
+        })
+    }
+}
+
+/**
+One ``<tag>`` of ``tags``, ``series/tags``, ``related_tags`` and the like.
+*/
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct Tag {
+    pub name: String,
+    /// Such as ``geo``, see [`TagGroupId`](crate::endpoints::TagGroupId).
+    pub group_id: String,
+    pub notes: Option<String>,
+    /// As FRED writes it, such as ``2012-02-27 10:18:19-06``.
+    pub created: String,
+    pub popularity: u32,
+    pub series_count: u32,
+}
+
+// test: metadata_models_parse_fred_samples
+pub fn tags(bytes: IVec) -> Result<Vec<Tag>> { parse_all(&bytes) }
+
+impl FromRow for Tag {
+    const TAG: &'static str = "tag";
+    const JSON_KEY: &'static str = "tags";
+
+    fn from_row(row: &Row) -> Result<Self> {
+        Ok(Tag {
+            name: row.string("name")?,
+            group_id: row.string("group_id")?,
+            notes: row.optional("notes"),
+            created: row.string("created")?,
+            popularity: row.parse("popularity")?,
+            series_count: row.parse("series_count")?,
+        })
+    }
+}
+
+/**
+The context FRED reports on the root element of a response, such as the ``units`` of
+``series/observations``. Fields are ``None`` where the root element does not have the
+attribute or FRED reports a value this crate does not know. ``attributes`` keeps every
+value as FRED wrote it.
+```
+use fred_api::{endpoints::Units, models::envelope};
+
+let xml = r#"<observations realtime_start="2024-05-01" realtime_end="2024-05-01"
+    observation_start="1600-01-01" observation_end="9999-12-31" units="pch"
+    output_type="1" file_type="xml" order_by="observation_date" sort_order="asc"
+    count="84" offset="0" limit="100000"/>"#;
+let envelope = envelope(xml.as_bytes().into()).unwrap();
+assert_eq!(envelope.units, Some(Units::Pch));
+assert_eq!(envelope.count, Some(84));
+```
+*/
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(any(test, feature = "serde"), derive(serde::Serialize, serde::Deserialize))]
+pub struct Envelope {
+    /// The root element, such as ``observations``. Empty for JSON responses.
+    pub tag: String,
+    pub format: Format,
+    pub realtime_start: Option<Date>,
+    pub realtime_end: Option<Date>,
+    pub observation_start: Option<Date>,
+    pub observation_end: Option<Date>,
+    pub units: Option<Units>,
+    pub output_type: Option<OutputType>,
+    pub order_by: Option<OrderBy>,
+    pub sort_order: Option<SortOrder>,
+    pub count: Option<u32>,
+    pub offset: Option<u32>,
+    pub limit: Option<u32>,
+    pub attributes: Attributes,
+}
+
+impl Envelope {
+
+    /**
+    The paging of the response, when it has ``count``, ``offset`` and ``limit``.
+    */
+    pub fn page(&self) -> Option<PageInfo> {
+        Some(PageInfo { count: self.count?, offset: self.offset?, limit: self.limit? })
+    }
+}
+
+// test: envelope_reads_root_element
+pub fn envelope(bytes: IVec) -> Result<Envelope> {
+    let (tag, attributes) = root_attributes(&bytes)?;
+    fn get<T: FromStr>(attributes: &Attributes, name: &str) -> Option<T> {
+        attributes.get(name)?.parse().ok()
+    }
+    Ok(Envelope {
+        format: Format::detect(&bytes),
+        realtime_start: get(&attributes, "realtime_start"),
+        realtime_end: get(&attributes, "realtime_end"),
+        observation_start: get(&attributes, "observation_start"),
+        observation_end: get(&attributes, "observation_end"),
+        units: get(&attributes, "units"),
+        output_type: get(&attributes, "output_type"),
+        order_by: get(&attributes, "order_by"),
+        sort_order: get(&attributes, "sort_order"),
+        count: get(&attributes, "count"),
+        offset: get(&attributes, "offset"),
+        limit: get(&attributes, "limit"),
+        tag,
+        attributes,
+    })
+}
+
+/*
+A record built from the attributes of one XML element, or the members of one object in
+the JSON array `JSON_KEY`.
+*/
+trait FromRow: Sized {
+    const TAG: &'static str;
+    const JSON_KEY: &'static str;
+
+    fn from_row(row: &Row) -> Result<Self>;
+}
+
+struct Row {
+    tag: &'static str,
+    attributes: Attributes,
+}
+
+impl Row {
+
+    // Empty attributes count as missing, as in `FieldIter`.
+    fn optional_str(&self, name: &str) -> Option<&str> {
+        self.attributes.get(name).filter(|value| !value.is_empty())
+    }
+
+    fn required(&self, name: &str) -> Result<&str> {
+        self.optional_str(name).ok_or_else(|| Error::MissingAttribute {
+            tag: self.tag.to_string(),
+            attribute: name.to_string(),
+            src: src!("Missing attribute '{}' in tag '{}'", name, self.tag),
+        })
+    }
+
+    fn string(&self, name: &str) -> Result<String> { self.required(name).map(str::to_string) }
+
+    fn optional(&self, name: &str) -> Option<String> { self.optional_str(name).map(str::to_string) }
+
+    fn parse<T: FromStr>(&self, name: &str) -> Result<T> {
+        let value = self.required(name)?;
+        value
+            .parse()
+            .map_err(|_| Error::Xml(src!("Could not parse {name} '{value}' in tag '{}'", self.tag)))
+    }
+
+    fn parse_optional<T: FromStr>(&self, name: &str) -> Result<Option<T>> {
+        match self.optional_str(name) {
+            Some(_) => self.parse(name).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+// test: metadata_models_parse_fred_samples
+fn parse_all<T: FromRow>(bytes: &IVec) -> Result<Vec<T>> {
+    let row = |attributes| T::from_row(&Row { tag: T::TAG, attributes });
+    match Format::detect(bytes) {
+        Format::Xml => AttributeIter::new(T::TAG, bytes.clone()).map(|a| row(a?)).collect(),
+        Format::Json => json_rows(bytes, T::JSON_KEY)?.into_iter().map(row).collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{models::*, Error};
+
+    #[test]
+    fn date_parses_and_orders() {
+        let date: Date = "1971-04-01".parse().unwrap();
+        assert_eq!(date, Date::new(1971, 4, 1).unwrap());
+        assert_eq!(date.to_string(), "1971-04-01");
+        assert!(date < "1971-04-02".parse().unwrap());
+        assert!(date < Date::OPEN_END);
+        assert!(!date.is_open_end());
+
+        assert!(Date::new(2000, 2, 29).is_some());
+        assert!(Date::new(1900, 2, 29).is_none());
+        assert!(Date::new(2024, 13, 1).is_none());
+        for s in ["1971-4-01", "1971-04-01T00:00", "1971/04/01", "+971-04-01", ".", ""] {
+            assert!(matches!(s.parse::<Date>(), Err(Error::Config(_))), "{s}");
+        }
+    }
+
+    #[test]
+    fn observations_parses_missing_values() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8" ?>
+    <observations realtime_start="2024-05-01" realtime_end="2024-05-01" count="3">
+        <observation realtime_start="2024-05-01" realtime_end="2024-05-01" date="1929-01-01" value="1202.659"/>
+        <observation realtime_start="2024-05-01" realtime_end="9999-12-31" date="1930-01-01" value="."/>
+        <observation realtime_start="2024-05-01" realtime_end="9999-12-31" date="1931-01-01" value="-3"/>
+    </observations>"#;
+        let obs = observations(xml.as_bytes().into()).unwrap();
+        assert_eq!(obs.len(), 3);
+        assert_eq!(obs[0], Observation {
+            date: Date::new(1929, 1, 1).unwrap(),
+            value: Some(1202.659),
+            realtime_start: Date::new(2024, 5, 1).unwrap(),
+            realtime_end: Date::new(2024, 5, 1).unwrap(),
+        });
+        assert_eq!(obs[1].value, None);
+        assert!(obs[1].realtime_end.is_open_end());
+        assert_eq!(obs[2].value, Some(-3.0));
+
+        let bad = xml.replace("\"-3\"", "\"n/a\"");
+        let e = observations(bad.as_bytes().into()).unwrap_err();
+        assert!(matches!(e, Error::Xml(_)) && e.msg().contains("n/a"), "{e}");
+    }
+
+    #[test]
+    fn metadata_models_parse_fred_samples() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8" ?>
+<seriess realtime_start="2013-08-14" realtime_end="2013-08-14">
+  <series id="GNPCA" realtime_start="2013-08-14" realtime_end="2013-08-14" title="Real Gross National Product" observation_start="1929-01-01" observation_end="2012-01-01" frequency="Annual" frequency_short="A" units="Billions of Chained 2009 Dollars" units_short="Bil. of Chn. 2009 $" seasonal_adjustment="Not Seasonally Adjusted" seasonal_adjustment_short="NSA" last_updated="2013-07-31 09:26:16-05" popularity="39" notes="BEA Account Code: A001RX1 &amp; more"/>
+</seriess>"#;
+        let series = series(xml.as_bytes().into()).unwrap();
+        assert_eq!(series.len(), 1);
+        assert_eq!((series[0].id.as_str(), series[0].frequency_short.as_str()), ("GNPCA", "A"));
+        assert_eq!(series[0].observation_start, Date::new(1929, 1, 1).unwrap());
+        assert_eq!((series[0].popularity, series[0].group_popularity), (39, None));
+        assert_eq!(series[0].notes.as_deref(), Some("BEA Account Code: A001RX1 & more"));
+
+        let xml = r#"<categories>
+  <category id="32991" name="Money, Banking, &amp; Finance" parent_id="0"/>
+  <category id="125" name="Trade Balance" parent_id="13" notes=""/>
+</categories>"#;
+        assert_eq!(categories(xml.as_bytes().into()).unwrap(), vec![
+            Category { id: 32991, name: "Money, Banking, & Finance".into(), parent_id: 0, notes: None },
+            Category { id: 125, name: "Trade Balance".into(), parent_id: 13, notes: None },
+        ]);
+
+        let xml = r#"<releases realtime_start="2013-08-13" realtime_end="2013-08-13">
+  <release id="9" realtime_start="2013-08-13" realtime_end="9999-12-31" name="Advance Monthly Sales for Retail and Food Services" press_release="true" link="http://www.census.gov/retail/"/>
+  <release id="13" realtime_start="2013-08-13" realtime_end="9999-12-31" name="G.17 Industrial Production and Capacity Utilization"/>
+</releases>"#;
+        let releases = releases(xml.as_bytes().into()).unwrap();
+        assert_eq!(releases[0].press_release, Some(true));
+        assert!(releases[0].realtime_end.is_open_end());
+        assert_eq!(releases[0].link.as_deref(), Some("http://www.census.gov/retail/"));
+        assert_eq!((releases[1].press_release, releases[1].link.as_ref()), (None, None));
+
+        let xml = r#"<sources>
+  <source id="1" realtime_start="2013-08-14" realtime_end="2013-08-14" name="Board of Governors of the Federal Reserve System" link="http://www.federalreserve.gov/"/>
+</sources>"#;
+        assert_eq!(sources(xml.as_bytes().into()).unwrap()[0].id, 1);
+
+        let xml = r#"<tags count="2">
+  <tag name="nation" group_id="geot" notes="Country Level" created="2012-02-27 10:18:19-06" popularity="100" series_count="105200"/>
+  <tag name="usa" group_id="geo" notes="" created="2012-02-27 10:18:19-06" popularity="100" series_count="50000"/>
+</tags>"#;
+        let tags = tags(xml.as_bytes().into()).unwrap();
+        assert_eq!((tags[0].name.as_str(), tags[0].series_count), ("nation", 105200));
+        assert_eq!((tags[1].group_id.as_str(), tags[1].notes.as_ref()), ("geo", None));
+
+        // Required attributes are checked as in `FieldIter`.
+        let e = categories("<category id=\"1\" name=\"x\"/>".as_bytes().into()).unwrap_err();
+        assert!(matches!(e, Error::MissingAttribute { ref attribute, .. } if attribute == "parent_id"));
+    }
+
+    #[test]
+    fn models_read_json_responses() {
+        let json = r#"{"realtime_start":"2024-05-01","realtime_end":"2024-05-01","count":2,"observations":[
+            {"realtime_start":"2024-05-01","realtime_end":"2024-05-01","date":"1929-01-01","value":"1202.659"},
+            {"realtime_start":"2024-05-01","realtime_end":"9999-12-31","date":"1930-01-01","value":"."}]}"#;
+        let obs = observations(json.as_bytes().into()).unwrap();
+        assert_eq!((obs[0].value, obs[1].value), (Some(1202.659), None));
+        assert!(obs[1].realtime_end.is_open_end());
+
+        let json = r#"{"seriess":[{"id":"GNPCA","realtime_start":"2013-08-14","realtime_end":"2013-08-14","title":"Real Gross National Product","observation_start":"1929-01-01","observation_end":"2012-01-01","frequency":"Annual","frequency_short":"A","units":"Billions of Chained 2009 Dollars","units_short":"Bil. of Chn. 2009 $","seasonal_adjustment":"Not Seasonally Adjusted","seasonal_adjustment_short":"NSA","last_updated":"2013-07-31 09:26:16-05","popularity":39,"group_popularity":39,"notes":"BEA Account Code: A001RX1"}]}"#;
+        let series = series(json.as_bytes().into()).unwrap();
+        assert_eq!((series[0].popularity, series[0].group_popularity), (39, Some(39)));
+
+        let json = r#"{"releases":[{"id":9,"realtime_start":"2013-08-13","realtime_end":"9999-12-31","name":"Retail Sales","press_release":true}]}"#;
+        let release = &releases(json.as_bytes().into()).unwrap()[0];
+        assert_eq!((release.press_release, release.link.as_ref()), (Some(true), None));
+    }
+
+    #[test]
+    fn models_round_trip_through_serde() {
+        let obs = Observation {
+            date: Date::new(1930, 1, 1).unwrap(),
+            value: None,
+            realtime_start: Date::new(2024, 5, 1).unwrap(),
+            realtime_end: Date::OPEN_END,
+        };
+        let json = serde_json::to_string(&obs).unwrap();
+        assert_eq!(
+            json,
+            r#"{"date":"1930-01-01","value":null,"realtime_start":"2024-05-01","realtime_end":"9999-12-31"}"#,
+        );
+        assert_eq!(serde_json::from_str::<Observation>(&json).unwrap(), obs);
+        assert!(serde_json::from_str::<Date>(r#""1930-02-30""#).is_err());
+
+        let category = Category { id: 125, name: "Trade Balance".into(), parent_id: 13, notes: None };
+        let json = serde_json::to_string(&category).unwrap();
+        assert_eq!(serde_json::from_str::<Category>(&json).unwrap(), category);
+    }
+
+    #[test]
+    fn envelope_reads_root_element() {
+        use crate::{endpoints::*, Format};
+
+        let xml = r#"<?xml version="1.0" encoding="utf-8" ?>
+<observations realtime_start="2024-05-01" realtime_end="2024-05-01" observation_start="1600-01-01" observation_end="9999-12-31" units="lin" output_type="1" file_type="xml" order_by="observation_date" sort_order="asc" count="84" offset="0" limit="100000">
+  <observation realtime_start="2024-05-01" realtime_end="2024-05-01" date="1929-01-01" value="1202.659"/>
+</observations>"#;
+        let root = envelope(xml.as_bytes().into()).unwrap();
+        assert_eq!(root.tag, "observations");
+        assert_eq!((root.format, root.units), (Format::Xml, Some(Units::Lin)));
+        assert_eq!(root.output_type, Some(OutputType::RealtimePeriod));
+        assert_eq!(root.order_by, Some(OrderBy::ObservationDate));
+        assert_eq!(root.sort_order, Some(SortOrder::Asc));
+        assert!(root.observation_end.unwrap().is_open_end());
+        assert_eq!(root.page().unwrap().next_offset(), None);
+        assert_eq!(root.attributes.get("file_type"), Some("xml"));
+
+        let json = r#"{"realtime_start":"2024-05-01","units":"pc1","output_type":1,"order_by":"observation_date","count":84,"offset":0,"limit":10,"observations":[]}"#;
+        let root = envelope(json.as_bytes().into()).unwrap();
+        assert_eq!((root.format, root.units), (Format::Json, Some(Units::Pc1)));
+        assert_eq!(root.output_type, Some(OutputType::RealtimePeriod));
+        assert_eq!(root.page().unwrap().next_offset(), Some(10));
+        assert_eq!(root.realtime_end, None);
+
+        // Values this crate does not know are kept in the attributes.
+        let root = envelope(r#"<seriess units="new" />"#.as_bytes().into()).unwrap();
+        assert_eq!((root.units, root.attributes.get("units")), (None, Some("new")));
+        let json = serde_json::to_string(&root).unwrap();
+        assert_eq!(serde_json::from_str::<Envelope>(&json).unwrap(), root);
+    }
+}
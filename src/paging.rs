@@ -0,0 +1,301 @@
+/*!
+Following ``offset`` through endpoints that return results a page at a time.
+*/
+
+use {
+    crate::{
+        format::{json_key, json_rows, root_attributes},
+        src, Attributes, DebugErr, Error, FieldIter, Format, FredClient, Lookup, RequestSpec,
+        Result,
+    },
+    sled::IVec,
+    std::vec,
+};
+
+/**
+The ``count``, ``offset`` and ``limit`` a paged response reports on its root element.
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PageInfo {
+    /// Results across every page.
+    pub count: u32,
+    pub offset: u32,
+    pub limit: u32,
+}
+
+impl PageInfo {
+
+    /**
+    ``None`` unless the root element has all of ``count``, ``offset`` and ``limit``.
+    */
+    // test: page_info_reads_root_element
+    pub fn of(bytes: &[u8]) -> Option<Self> {
+        let (_, root) = root_attributes(bytes).ok()?;
+        let number = |name| root.get(name)?.parse().ok();
+        Some(PageInfo {
+            count: number("count")?,
+            offset: number("offset")?,
+            limit: number("limit")?,
+        })
+    }
+
+    /**
+    The offset of the following page, if there is one.
+    */
+    pub fn next_offset(&self) -> Option<u32> {
+        let next = self.offset.checked_add(self.limit)?;
+        (self.limit > 0 && next < self.count).then_some(next)
+    }
+}
+
+/**
+The pages of a request, fetched one at a time through the client's cache and lookup as
+the previous page's [`PageInfo`] calls for them. Responses without page information are
+a single page. See [`FredClient::pages`].
+*/
+#[derive(Debug)]
+pub struct Pages {
+    client: FredClient,
+    req: RequestSpec,
+    lookup: Lookup,
+    next: Option<RequestSpec>,
+    info: Option<PageInfo>,
+}
+
+impl Pages {
+    pub(crate) fn new(client: FredClient, req: RequestSpec, lookup: Lookup) -> Self {
+        Pages { client, next: Some(req.clone()), req, lookup, info: None }
+    }
+
+    /**
+    The body of the next page, or ``None`` after the last. Iteration ends after an
+    error.
+    */
+    // test: pages_follow_offset
+    pub async fn next_page(&mut self) -> Option<Result<IVec>> {
+        let req = self.next.take()?;
+        let page = match self.client.send(&req, self.lookup).await {
+            Ok(page) => page,
+            Err(e) => return Some(Err(e)),
+        };
+        self.info = PageInfo::of(&page);
+        self.next = self
+            .info
+            .and_then(|info| info.next_offset())
+            .map(|offset| self.req.with_param("offset", &offset.to_string()));
+        Some(Ok(page))
+    }
+
+    /**
+    What the last page reported, ``None`` before the first page or if it had none.
+    */
+    pub fn info(&self) -> Option<PageInfo> { self.info }
+
+    /**
+    Each page's ``fields`` of the elements named ``tag``, as one sequence of rows. In
+    JSON pages the fields are members of the objects in the array for ``tag``, such as
+    ``seriess`` for ``series``.
+    */
+    // test: pages_follow_offset
+    pub fn rows(self, tag: &str, fields: Vec<&str>) -> Rows {
+        Rows {
+            pages: self,
+            tag: tag.to_string(),
+            fields: fields.into_iter().map(str::to_string).collect(),
+            rows: None,
+        }
+    }
+}
+
+/**
+The rows of every page, see [`Pages::rows`].
+*/
+#[derive(Debug)]
+pub struct Rows {
+    pages: Pages,
+    tag: String,
+    fields: Vec<String>,
+    rows: Option<PageRows>,
+}
+
+// The rows of one page.
+#[derive(Debug)]
+enum PageRows {
+    Xml(Box<FieldIter>),
+    Json(vec::IntoIter<Result<Vec<String>>>),
+}
+
+impl Iterator for PageRows {
+    type Item = Result<Vec<String>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            PageRows::Xml(rows) => rows.next(),
+            PageRows::Json(rows) => rows.next(),
+        }
+    }
+}
+
+impl Rows {
+
+    /**
+    The next row, fetching the next page when this one is used up. A bad row or page
+    ends iteration.
+    */
+    // test: pages_follow_offset
+    pub async fn next(&mut self) -> Option<Result<Vec<String>>> {
+        loop {
+            if let Some(row) = self.rows.as_mut().and_then(Iterator::next) {
+                if row.is_err() {
+                    self.pages.next = None;
+                    self.rows = None;
+                }
+                return Some(row);
+            }
+            let page = match self.pages.next_page().await? {
+                Ok(page) => page,
+                Err(e) => return Some(Err(e)),
+            };
+            self.rows = match Format::detect(&page) {
+                Format::Xml => {
+                    let fields = self.fields.iter().map(String::as_str).collect();
+                    Some(PageRows::Xml(Box::new(FieldIter::new(&self.tag, fields, page))))
+                }
+                Format::Json => match json_rows(&page, &json_key(&self.tag)) {
+                    Ok(rows) => {
+                        let rows: Vec<_> = rows.iter().map(|row| self.json_row(row)).collect();
+                        Some(PageRows::Json(rows.into_iter()))
+                    }
+                    Err(e) => {
+                        self.pages.next = None;
+                        return Some(Err(e));
+                    }
+                },
+            };
+        }
+    }
+
+    // The fields of one object of a JSON page, checked as `FieldIter` checks attributes.
+    fn json_row(&self, row: &Attributes) -> Result<Vec<String>> {
+        self.fields
+            .iter()
+            .map(|field| {
+                let problem = match row.get(field) {
+                    Some(value) if !value.is_empty() => return Ok(value.to_string()),
+                    Some(_) => "Empty",
+                    None => "Missing",
+                };
+                Err(Error::MissingAttribute {
+                    tag: self.tag.clone(),
+                    attribute: field.clone(),
+                    src: src!("{} attribute '{}' in tag '{}'", problem, field, self.tag),
+                })
+            })
+            .collect()
+    }
+
+    /**
+    Every remaining row, or the first error.
+    */
+    pub async fn try_collect(mut self) -> Result<Vec<Vec<String>>> {
+        let mut rows = Vec::new();
+        while let Some(row) = self.next().await {
+            rows.push(row?);
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{paging::PageInfo, testing::MockServer, *};
+
+    #[test]
+    fn page_info_reads_root_element() {
+        let xml = br#"<?xml version="1.0" encoding="utf-8" ?>
+<seriess realtime_start="2013-08-14" order_by="series_id" count="2500" offset="1000" limit="1000">
+  <series id="A"/>
+</seriess>"#;
+        let info = PageInfo::of(xml).unwrap();
+        assert_eq!(info, PageInfo { count: 2500, offset: 1000, limit: 1000 });
+        assert_eq!(info.next_offset(), Some(2000));
+        assert_eq!(PageInfo { offset: 2000, ..info }.next_offset(), None);
+        assert_eq!(PageInfo { limit: 0, ..info }.next_offset(), None);
+
+        let json = br#"{"count":2500,"offset":0,"limit":1000,"seriess":[]}"#;
+        assert_eq!(PageInfo::of(json).unwrap().next_offset(), Some(1000));
+        assert_eq!(PageInfo::of(br#"<series id="GNPCA"/>"#), None);
+    }
+
+    #[tokio::test]
+    async fn pages_follow_offset() {
+        let server = MockServer::start().await.unwrap();
+        let page = |offset: u32, ids: &[&str]| {
+            let rows: String = ids.iter().map(|id| format!(r#"<series id="{id}"/>"#)).collect();
+            format!(r#"<seriess count="5" offset="{offset}" limit="2">{rows}</seriess>"#)
+        };
+        server.insert("tags/series?tag_names=food&", 200, page(0, &["A", "B"]));
+        server.insert("tags/series?tag_names=food&offset=2&", 200, page(2, &["C", "D"]));
+        server.insert("tags/series?tag_names=food&offset=4&", 200, page(4, &["E"]));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let client = FredClient::new(sled::open(dir.path()).unwrap(), Some("abcd")).unwrap()
+            .with_base_uri(&server.base_uri()).unwrap()
+            .allow_http(true).unwrap()
+            .with_rate_limit(None);
+        let req = client.request("tags/series?tag_names=food&").unwrap();
+
+        let rows = client.pages(&req, Lookup::FredOnCacheMiss)
+            .rows("series", vec!["id"])
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(rows.concat(), ["A", "B", "C", "D", "E"]);
+        assert_eq!(server.hits(), 3);
+
+        // Later pages are cached like any request.
+        let mut pages = client.pages(&req, Lookup::CacheOnly);
+        let mut count = 0;
+        while let Some(page) = pages.next_page().await {
+            page.unwrap();
+            count += 1;
+        }
+        assert_eq!(count, 3);
+        assert_eq!(pages.info(), Some(PageInfo { count: 5, offset: 4, limit: 2 }));
+        assert_eq!(server.hits(), 3);
+    }
+
+    #[tokio::test]
+    async fn pages_read_json_rows() {
+        let server = MockServer::start().await.unwrap();
+        let page = |offset: u32, ids: &[&str]| {
+            let rows: Vec<_> =
+                ids.iter().map(|id| format!(r#"{{"id":"{id}","popularity":3}}"#)).collect();
+            let rows = rows.join(",");
+            format!(r#"{{"count":3,"offset":{offset},"limit":2,"seriess":[{rows}]}}"#)
+        };
+        let mid_part = "tags/series?tag_names=food&file_type=json&";
+        server.insert(mid_part, 200, page(0, &["A", "B"]));
+        server.insert(&format!("{mid_part}offset=2&"), 200, page(2, &["C"]));
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let client = FredClient::new(sled::open(dir.path()).unwrap(), Some("abcd")).unwrap()
+            .with_base_uri(&server.base_uri()).unwrap()
+            .allow_http(true).unwrap()
+            .with_rate_limit(None);
+        let req = client.request(mid_part).unwrap();
+
+        let rows = client.pages(&req, Lookup::FredOnCacheMiss)
+            .rows("series", vec!["id", "popularity"])
+            .try_collect()
+            .await
+            .unwrap();
+        assert_eq!(rows, [["A", "3"], ["B", "3"], ["C", "3"]]);
+        assert_eq!(server.hits(), 2);
+
+        // A field missing from the objects is an error, as for XML.
+        let mut rows = client.pages(&req, Lookup::CacheOnly).rows("series", vec!["title"]);
+        assert!(matches!(rows.next().await, Some(Err(Error::MissingAttribute { .. }))));
+        assert!(rows.next().await.is_none());
+    }
+}
@@ -0,0 +1,144 @@
+/*!
+A token bucket limiting the rate of requests to FRED.
+*/
+
+use {
+    std::{
+        fmt,
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+    tokio::time::{sleep, Instant},
+};
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+/**
+A token bucket shared by its clones, so one limiter can pace every task and client
+that holds a copy. Tokens refill at ``per_minute`` and at most ``burst`` accumulate.
+When the bucket is empty callers queue in the order they arrived.
+*/
+#[derive(Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+    per_second: f64,
+    burst: f64,
+}
+
+impl RateLimiter {
+
+    /**
+    FRED allows 120 requests per minute.
+    */
+    pub const FRED_PER_MINUTE: u32 = 120;
+
+    // test: rate_limiter_queues_past_burst
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        RateLimiter {
+            bucket: Arc::new(Mutex::new(Bucket { tokens: burst, last: Instant::now() })),
+            per_second: f64::from(per_minute.max(1)) / 60.0,
+            burst,
+        }
+    }
+
+    /**
+    Wait for a token.
+    */
+    // test: rate_limiter_queues_past_burst
+    pub async fn acquire(&self) {
+        let wait = {
+            let mut bucket = self.bucket.lock().unwrap();
+            self.refill(&mut bucket);
+            // A negative balance reserves a place in the queue.
+            bucket.tokens -= 1.0;
+            if bucket.tokens >= 0.0 {
+                return;
+            }
+            Duration::from_secs_f64(-bucket.tokens / self.per_second)
+        };
+        sleep(wait).await;
+    }
+
+    /**
+    Take a token if one is available without waiting.
+    */
+    // test: rate_limiter_is_shared_by_clones
+    pub fn try_acquire(&self) -> bool {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn refill(&self, bucket: &mut Bucket) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(self.burst);
+        bucket.last = now;
+    }
+}
+
+/**
+120 requests per minute, spaced evenly.
+*/
+impl Default for RateLimiter {
+    fn default() -> Self { RateLimiter::new(RateLimiter::FRED_PER_MINUTE, 1) }
+}
+
+impl fmt::Debug for RateLimiter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RateLimiter")
+            .field("per_minute", &(self.per_second * 60.0))
+            .field("burst", &self.burst)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limiter_queues_past_burst() {
+        let limiter = RateLimiter::new(60, 2);
+        let start = Instant::now();
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+
+        // One token a second after the burst.
+        limiter.acquire().await;
+        limiter.acquire().await;
+        let elapsed = start.elapsed().as_secs_f64();
+        assert!((elapsed - 2.0).abs() < 0.01, "{elapsed}");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn rate_limiter_is_shared_by_clones() {
+        let limiter = RateLimiter::new(60, 1);
+        let clone = limiter.clone();
+        assert!(clone.try_acquire());
+        assert!(!limiter.try_acquire());
+
+        // Tasks queue on the same bucket.
+        let start = Instant::now();
+        let tasks: Vec<_> = (0..3)
+            .map(|_| {
+                let limiter = limiter.clone();
+                tokio::spawn(async move { limiter.acquire().await; start.elapsed() })
+            })
+            .collect();
+        let mut elapsed = Vec::new();
+        for task in tasks { elapsed.push(task.await.unwrap().as_secs_f64().round()) }
+        elapsed.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(elapsed, vec![1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,153 @@
+/*!
+Retrying transient failures of requests to FRED with exponential backoff.
+*/
+
+use {
+    http::{header::RETRY_AFTER, HeaderMap, StatusCode},
+    std::{
+        collections::hash_map::RandomState,
+        hash::{BuildHasher, Hasher},
+        time::{Duration, SystemTime},
+    },
+};
+
+/**
+How often and how long to wait before repeating a request that failed for a transient
+reason: a transport error, or a 429, 500, 502, 503 or 504 response. Other responses,
+such as a 400 for a bad ``series_id``, are never retried.
+
+The wait before attempt ``n + 1`` is ``base_delay * 2^(n - 1)``, capped at ``max_delay``
+and reduced by up to the ``jitter`` fraction at random. A ``Retry-After`` header on the
+response replaces the backoff when ``honor_retry_after`` is set, still capped at
+``max_delay`` so that a server cannot stall a request for hours.
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts including the first, so ``1`` never retries.
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Between ``0.0`` and ``1.0``.
+    pub jitter: f64,
+    pub honor_retry_after: bool,
+}
+
+impl RetryPolicy {
+
+    /**
+    A single attempt.
+    */
+    pub fn none() -> Self {
+        RetryPolicy { max_attempts: 1, ..RetryPolicy::default() }
+    }
+
+    /**
+    Whether a response status is worth retrying.
+    */
+    // test: transient_statuses
+    pub fn is_transient(status: StatusCode) -> bool {
+        matches!(status.as_u16(), 429 | 500 | 502 | 503 | 504)
+    }
+
+    /**
+    The wait after failed attempt number ``attempt``, starting at 1.
+    */
+    // test: delay_backs_off_exponentially
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        if let (true, Some(retry_after)) = (self.honor_retry_after, retry_after) {
+            return retry_after.min(self.max_delay);
+        }
+        let exp = attempt.saturating_sub(1).min(31);
+        let backoff = self.base_delay.saturating_mul(1 << exp).min(self.max_delay);
+        let jitter = self.jitter.clamp(0.0, 1.0) * random_fraction();
+        backoff.mul_f64(1.0 - jitter)
+    }
+}
+
+/**
+Four attempts, waiting about 0.5, 1 and 2 seconds in between.
+*/
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: 0.25,
+            honor_retry_after: true,
+        }
+    }
+}
+
+// A fraction in [0, 1) from the randomly seeded std hasher.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/**
+The ``Retry-After`` header as seconds or an HTTP date.
+*/
+// test: retry_after_parses_seconds_and_dates
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(SystemTime::now()).unwrap_or(Duration::ZERO))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transient_statuses() {
+        for status in [429, 500, 502, 503, 504] {
+            assert!(RetryPolicy::is_transient(StatusCode::from_u16(status).unwrap()));
+        }
+        for status in [200, 400, 401, 404, 423] {
+            assert!(!RetryPolicy::is_transient(StatusCode::from_u16(status).unwrap()));
+        }
+    }
+
+    #[test]
+    fn delay_backs_off_exponentially() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(350),
+            jitter: 0.0,
+            ..RetryPolicy::default()
+        };
+        let delays: Vec<_> = (1..=4).map(|n| policy.delay(n, None).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 350, 350]);
+        assert_eq!(policy.delay(1, Some(Duration::from_millis(70))), Duration::from_millis(70));
+        assert_eq!(policy.delay(1, Some(Duration::from_secs(86_400))), Duration::from_millis(350));
+
+        let policy = RetryPolicy { jitter: 0.5, ..policy };
+        for _ in 0..100 {
+            let delay = policy.delay(2, None).as_millis();
+            assert!((100..=200).contains(&delay), "{delay}");
+        }
+    }
+
+    #[test]
+    fn retry_after_parses_seconds_and_dates() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::from_secs(120)));
+
+        // Dates in the past mean now.
+        headers.insert(RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(retry_after(&headers), Some(Duration::ZERO));
+
+        let later = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(60));
+        headers.insert(RETRY_AFTER, later.parse().unwrap());
+        let secs = retry_after(&headers).unwrap().as_secs();
+        assert!((58..=60).contains(&secs), "{secs}");
+    }
+}
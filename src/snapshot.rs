@@ -0,0 +1,288 @@
+/*!
+A cache of plain files meant to be read by people and kept under version control.
+*/
+
+use {
+    crate::{
+        src,
+        store::{io_error, read_file, remove_file, sha256_hex, write_file},
+        CacheEntry, CacheStore, DebugErr, EntryMeta, Error, Format, Result,
+    },
+    sled::IVec,
+    std::{
+        collections::BTreeMap,
+        fmt::Write,
+        fs,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+    },
+};
+
+/**
+The file listing every current entry.
+*/
+const INDEX_FILE: &str = "index.txt";
+
+/**
+A store for checking a snapshot of FRED responses into a repository. Each response is a
+file named by the SHA-256 of its canonical request, with an ``.xml`` or ``.json``
+extension for its format and its metadata in a ``.meta`` file alongside. ``index.txt``
+lists each canonical request with its file, one per line sorted by request,
+so adding or refreshing a response changes one line. Replaced entries are kept under
+``previous``.
+```text
+dir/index.txt
+dir/3f/3f6c…e1.xml
+dir/3f/3f6c…e1.meta
+```
+Clones share a lock on the index, but separate processes writing to one directory at the
+same time can lose lines of it.
+```
+use fred_api::{build_request, cache_request, SnapshotStore};
+
+let dir = tempfile::TempDir::new().unwrap();
+let store = SnapshotStore::new(dir.path()).unwrap();
+let req = build_request("series?series_id=GNPCA&", Some("abcd")).unwrap();
+assert!(cache_request(&req, &store).unwrap().is_none());
+```
+*/
+#[derive(Clone, Debug)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    index: Arc<Mutex<()>>,
+}
+
+impl SnapshotStore {
+
+    /**
+    A store in ``dir``, creating it if need be.
+    */
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join("previous")).map_err(|e| io_error(&dir, e))?;
+        Ok(SnapshotStore { dir, index: Arc::new(Mutex::new(())) })
+    }
+
+    pub fn dir(&self) -> &Path { &self.dir }
+
+    /**
+    The canonical requests in the index with their files, relative to the directory.
+    */
+    // test: snapshot_store_writes_readable_files
+    pub fn index(&self) -> Result<BTreeMap<String, String>> {
+        let path = self.dir.join(INDEX_FILE);
+        let Some(bytes) = read_file(&path)? else { return Ok(BTreeMap::new()) };
+        let text = String::from_utf8(bytes)
+            .map_err(|e| Error::Cache(src!("'{}' is not UTF-8: {e}", path.display())))?;
+        text.lines()
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                let (mid_part, file) = line.split_once(' ').ok_or_else(|| {
+                    Error::Cache(src!("Bad line '{line}' in '{}'", path.display()))
+                })?;
+                Ok((mid_part.to_string(), file.trim_start().to_string()))
+            })
+            .collect()
+    }
+
+    fn write_index(&self, index: &BTreeMap<String, String>) -> Result<()> {
+        let mut text = String::new();
+        for (mid_part, file) in index {
+            let _ = writeln!(text, "{mid_part} {file}");
+        }
+        write_file(&self.dir.join(INDEX_FILE), text.as_bytes())
+    }
+
+    fn update_index(&self, key: &str, file: Option<String>) -> Result<()> {
+        let _lock = self.index.lock().unwrap();
+        let mut index = self.index()?;
+        let changed = match file {
+            Some(file) => index.insert(key.to_string(), file.clone()) != Some(file),
+            None => index.remove(key).is_some(),
+        };
+        if changed {
+            self.write_index(&index)?;
+        }
+        Ok(())
+    }
+}
+
+/*
+The file of `key` relative to a directory, without an extension: the SHA-256 in hex
+under a directory named by its first two digits.
+*/
+// test: snapshot_store_writes_readable_files
+fn stem(key: &[u8]) -> String {
+    let hex = sha256_hex(key);
+    format!("{}/{hex}", &hex[..2])
+}
+
+// Keys become lines of the index, so they must be one line of text without spaces.
+fn key_str(key: &[u8]) -> Result<&str> {
+    std::str::from_utf8(key)
+        .ok()
+        .filter(|key| !key.is_empty() && !key.contains(char::is_whitespace))
+        .ok_or_else(|| {
+            Error::Cache(src!("Key '{}' cannot be indexed", String::from_utf8_lossy(key)))
+        })
+}
+
+fn body_path(dir: &Path, stem: &str, format: Format) -> PathBuf {
+    dir.join(format!("{stem}.{format}"))
+}
+
+fn meta_path(dir: &Path, stem: &str) -> PathBuf {
+    dir.join(format!("{stem}.meta"))
+}
+
+fn read(dir: &Path, stem: &str) -> Result<Option<CacheEntry>> {
+    let meta = match read_file(&meta_path(dir, stem))? {
+        Some(bytes) => Some(EntryMeta::from_bytes(&bytes)?),
+        None => None,
+    };
+    // Only the body the metadata describes, as a refresh in another format leaves the old
+    // body in place until the new metadata is written.
+    let formats = match &meta {
+        Some(meta) => vec![meta.format],
+        None => vec![Format::Xml, Format::Json],
+    };
+    for format in formats {
+        let Some(body) = read_file(&body_path(dir, stem, format))? else { continue };
+        return Ok(Some(CacheEntry { body: body.into(), meta }));
+    }
+    Ok(None)
+}
+
+/*
+Writes `entry` and returns the file of its body. Each file is renamed into place over the
+old one, body first, and only then is a body in the other format or metadata the entry
+does not have removed, so that readers never find the entry missing.
+*/
+fn write(dir: &Path, stem: &str, entry: &CacheEntry) -> Result<String> {
+    let format = match &entry.meta {
+        Some(meta) => meta.format,
+        None => Format::detect(&entry.body),
+    };
+    let path = body_path(dir, stem, format);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| io_error(parent, e))?;
+    }
+    write_file(&path, &entry.body)?;
+    let other = match format {
+        Format::Xml => Format::Json,
+        Format::Json => Format::Xml,
+    };
+    match &entry.meta {
+        Some(meta) => {
+            write_file(&meta_path(dir, stem), &meta.to_bytes())?;
+            remove_file(&body_path(dir, stem, other))?;
+        }
+        None => {
+            remove_file(&meta_path(dir, stem))?;
+            remove_file(&body_path(dir, stem, other))?;
+        }
+    }
+    Ok(format!("{stem}.{format}"))
+}
+
+fn remove(dir: &Path, stem: &str) -> Result<()> {
+    for format in [Format::Xml, Format::Json] {
+        remove_file(&body_path(dir, stem, format))?;
+    }
+    remove_file(&meta_path(dir, stem))
+}
+
+impl CacheStore for SnapshotStore {
+    fn get_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        read(&self.dir, &stem(key))
+    }
+
+    fn put_entry(&self, key: &[u8], entry: &CacheEntry, keep_previous: bool) -> Result<()> {
+        let key_str = key_str(key)?;
+        let stem = stem(key);
+        if keep_previous {
+            if let Some(old) = read(&self.dir, &stem)? {
+                write(&self.dir.join("previous"), &stem, &old)?;
+            }
+        }
+        let file = write(&self.dir, &stem, entry)?;
+        self.update_index(key_str, Some(file))
+    }
+
+    fn get_previous(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        read(&self.dir.join("previous"), &stem(key))
+    }
+
+    fn remove_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        let stem = stem(key);
+        let entry = read(&self.dir, &stem)?;
+        remove(&self.dir, &stem)?;
+        if let Ok(key) = key_str(key) {
+            self.update_index(key, None)?;
+        }
+        Ok(entry)
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<(IVec, CacheEntry)>> + '_> {
+        let index = match self.index() {
+            Ok(index) => index,
+            Err(e) => return Box::new(std::iter::once(Err(e))),
+        };
+        // Lines whose file has gone are left out.
+        Box::new(index.into_keys().filter_map(|key| {
+            self.get_entry(key.as_bytes())
+                .transpose()
+                .map(|entry| entry.map(|entry| (key.into_bytes().into(), entry)))
+        }))
+    }
+
+    fn store_id(&self) -> usize { Arc::as_ptr(&self.index) as usize }
+}
+
+#[cfg(test)]
+mod test {
+    use {super::stem, crate::*, std::fs, tempfile::TempDir};
+
+    #[test]
+    fn snapshot_store_writes_readable_files() {
+        let dir = TempDir::new().unwrap();
+        let store = SnapshotStore::new(dir.path()).unwrap();
+        let gnpca = b"series?series_id=GNPCA&";
+        let stem = stem(gnpca);
+        assert_eq!(stem.len(), 67);
+        assert_eq!(stem[..2], stem[3..5]);
+
+        let xml = CacheEntry { body: r#"<series id="GNPCA"/>"#.into(), meta: None };
+        store.put_entry(gnpca, &xml, false).unwrap();
+        let json = CacheEntry {
+            body: r#"{"seriess":[]}"#.into(),
+            meta: Some(EntryMeta { format: Format::Json, ..EntryMeta::new(200, 14) }),
+        };
+        store.put_entry(b"category?category_id=125&", &json, false).unwrap();
+
+        // The index is sorted text naming each file.
+        let index = fs::read_to_string(dir.path().join("index.txt")).unwrap();
+        let lines: Vec<_> = index.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("category?category_id=125& "));
+        assert!(lines[0].ends_with(".json"));
+        assert_eq!(lines[1], format!("series?series_id=GNPCA& {stem}.xml"));
+        let body = fs::read_to_string(dir.path().join(format!("{stem}.xml"))).unwrap();
+        assert_eq!(body, r#"<series id="GNPCA"/>"#);
+
+        // A refresh in another format replaces the file.
+        store.put_entry(gnpca, &json, true).unwrap();
+        assert!(!dir.path().join(format!("{stem}.xml")).exists());
+        let index = store.index().unwrap();
+        assert_eq!(index["series?series_id=GNPCA&"], format!("{stem}.json"));
+        assert_eq!(store.get_previous(gnpca).unwrap(), Some(xml.clone()));
+
+        // A body left in the other format is not read while the metadata names this one.
+        fs::write(dir.path().join(format!("{stem}.xml")), &xml.body).unwrap();
+        assert_eq!(store.get_entry(gnpca).unwrap().unwrap().body, json.body);
+
+        store.remove_entry(gnpca).unwrap();
+        assert_eq!(store.index().unwrap().len(), 1);
+        assert!(store.put_entry(b"bad key", &json, false).is_err());
+    }
+}
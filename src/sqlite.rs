@@ -0,0 +1,199 @@
+/*!
+A cache in one SQLite file, behind the ``sqlite`` feature.
+*/
+
+use {
+    crate::{src, CacheEntry, CacheStore, DebugErr, EntryMeta, Error, Result},
+    rusqlite::{params, Connection, OptionalExtension, TransactionBehavior},
+    sled::IVec,
+    std::{
+        path::Path,
+        sync::{Arc, Mutex},
+        time::Duration,
+    },
+};
+
+/**
+How long a write waits for another process to finish its own.
+*/
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/**
+A store in one SQLite file in WAL mode, so that several processes can read and write the
+same cache at once. Clones share one connection.
+```
+use fred_api::{build_request, cache_request, SqliteStore};
+
+let dir = tempfile::TempDir::new().unwrap();
+let store = SqliteStore::open(dir.path().join("fred.sqlite")).unwrap();
+let req = build_request("series?series_id=GNPCA&", Some("abcd")).unwrap();
+assert!(cache_request(&req, &store).unwrap().is_none());
+```
+*/
+#[derive(Clone, Debug)]
+pub struct SqliteStore {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl SqliteStore {
+
+    /**
+    Open or create the store at ``path``, for example a file in the directory given by
+    [`fred_cache`](crate::fred_cache). Fails if SQLite will not use WAL mode for it.
+    */
+    // test: sqlite_stores_share_one_file
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let conn = Connection::open(path).map_err(sqlite_error)?;
+        conn.busy_timeout(BUSY_TIMEOUT).map_err(sqlite_error)?;
+        let mode = conn
+            .pragma_update_and_check(None, "journal_mode", "WAL", |row| {
+                row.get::<_, String>(0)
+            })
+            .map_err(sqlite_error)?;
+        // SQLite keeps the old mode where WAL is not possible, as for an in-memory file.
+        if !mode.eq_ignore_ascii_case("wal") {
+            return Err(Error::Cache(src!("SQLite would not use WAL, only '{mode}'")));
+        }
+        conn.execute_batch(
+            "PRAGMA synchronous = NORMAL;
+            CREATE TABLE IF NOT EXISTS entries (
+                key BLOB PRIMARY KEY, body BLOB NOT NULL, meta BLOB
+            );
+            CREATE TABLE IF NOT EXISTS previous (
+                key BLOB PRIMARY KEY, body BLOB NOT NULL, meta BLOB
+            );",
+        )
+        .map_err(sqlite_error)?;
+        Ok(SqliteStore { conn: Arc::new(Mutex::new(conn)) })
+    }
+
+    fn read(&self, table: &str, key: &[u8]) -> Result<Option<CacheEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let row = conn
+            .query_row(
+                &format!("SELECT body, meta FROM {table} WHERE key = ?1"),
+                [key],
+                |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Option<Vec<u8>>>(1)?)),
+            )
+            .optional()
+            .map_err(sqlite_error)?;
+        row.map(entry).transpose()
+    }
+
+    fn keys(&self) -> rusqlite::Result<Vec<Vec<u8>>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT key FROM entries ORDER BY key")?;
+        let keys = stmt.query_map([], |row| row.get(0))?.collect();
+        keys
+    }
+}
+
+fn entry((body, meta): (Vec<u8>, Option<Vec<u8>>)) -> Result<CacheEntry> {
+    let meta = match meta {
+        Some(bytes) => Some(EntryMeta::from_bytes(&bytes)?),
+        None => None,
+    };
+    Ok(CacheEntry { body: body.into(), meta })
+}
+
+fn sqlite_error(e: rusqlite::Error) -> Error {
+    Error::Cache(src!("SQLite: {e}"))
+}
+
+impl CacheStore for SqliteStore {
+    fn get_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        self.read("entries", key)
+    }
+
+    fn put_entry(&self, key: &[u8], entry: &CacheEntry, keep_previous: bool) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        // Taking the write lock up front keeps two writers from deadlocking.
+        let tx = conn
+            .transaction_with_behavior(TransactionBehavior::Immediate)
+            .map_err(sqlite_error)?;
+        if keep_previous {
+            tx.execute(
+                "INSERT OR REPLACE INTO previous
+                SELECT key, body, meta FROM entries WHERE key = ?1",
+                [key],
+            )
+            .map_err(sqlite_error)?;
+        }
+        let meta = entry.meta.as_ref().map(EntryMeta::to_bytes);
+        tx.execute(
+            "INSERT OR REPLACE INTO entries (key, body, meta) VALUES (?1, ?2, ?3)",
+            params![key, &entry.body[..], meta],
+        )
+        .map_err(sqlite_error)?;
+        tx.commit().map_err(sqlite_error)
+    }
+
+    fn get_previous(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        self.read("previous", key)
+    }
+
+    fn remove_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let row = conn
+            .query_row(
+                "DELETE FROM entries WHERE key = ?1 RETURNING body, meta",
+                [key],
+                |row| Ok((row.get::<_, Vec<u8>>(0)?, row.get::<_, Option<Vec<u8>>>(1)?)),
+            )
+            .optional()
+            .map_err(sqlite_error)?;
+        row.map(entry).transpose()
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<(IVec, CacheEntry)>> + '_> {
+        // Keys first, so the connection is not held while the caller works.
+        let keys = match self.keys() {
+            Ok(keys) => keys,
+            Err(e) => return Box::new(std::iter::once(Err(sqlite_error(e)))),
+        };
+        // Entries removed since the keys were read are left out.
+        Box::new(keys.into_iter().filter_map(|key| {
+            self.get_entry(&key)
+                .transpose()
+                .map(|entry| entry.map(|entry| (key.into(), entry)))
+        }))
+    }
+
+    fn store_id(&self) -> usize { Arc::as_ptr(&self.conn) as usize }
+}
+
+#[cfg(test)]
+mod test {
+    use {crate::*, std::thread, tempfile::TempDir};
+
+    #[test]
+    fn sqlite_stores_share_one_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("fred.sqlite");
+        let writers: Vec<_> = (0..4)
+            .map(|i| {
+                // A connection each, as separate processes would have.
+                let store = SqliteStore::open(&path).unwrap();
+                thread::spawn(move || {
+                    for j in 0..20 {
+                        let key = format!("series?series_id=S{i}_{j}&");
+                        let entry = CacheEntry { body: "<series/>".into(), meta: None };
+                        store.put_entry(key.as_bytes(), &entry, true).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let store = SqliteStore::open(&path).unwrap();
+        assert_eq!(store.entries().count(), 80);
+        let mode: String = store.conn.lock().unwrap()
+            .query_row("PRAGMA journal_mode", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode, "wal");
+
+        // Without WAL several processes cannot share the file.
+        assert!(matches!(SqliteStore::open(":memory:"), Err(Error::Cache(_))));
+    }
+}
@@ -0,0 +1,498 @@
+/*!
+Where cached responses are kept. [`CacheStore`] is implemented by ``sled::Db``, the
+default, by [`MemoryStore`], by [`FsStore`], by
+[`SnapshotStore`](crate::SnapshotStore) and, with the ``sqlite`` feature, by
+``SqliteStore``. [`LruStore`](crate::LruStore) keeps recent entries of any of them in
+memory.
+*/
+
+use {
+    crate::{src, CacheEntry, DebugErr, EntryMeta, Error, Result},
+    ring::digest::{digest, SHA256},
+    sled::{
+        transaction::{TransactionError, Transactional},
+        Db, IVec, Tree,
+    },
+    std::{
+        collections::BTreeMap,
+        fmt::Write,
+        fs, io,
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicU64, Ordering},
+            Arc, Mutex,
+        },
+    },
+};
+
+/**
+A store of cache entries keyed by canonical request, see
+[`RequestSpec::ivec`](crate::RequestSpec::ivec). Each key has a current entry and, once
+replaced with ``keep_previous``, a previous entry.
+```
+use fred_api::{CacheEntry, CacheStore, EntryMeta, MemoryStore};
+
+let store = MemoryStore::new();
+let entry = CacheEntry { body: "<series/>".into(), meta: Some(EntryMeta::new(200, 9)) };
+store.put_entry(b"series?series_id=GNPCA&", &entry, false).unwrap();
+assert_eq!(store.get_entry(b"series?series_id=GNPCA&").unwrap().unwrap().body, "<series/>");
+```
+*/
+// test: stores_behave_alike
+pub trait CacheStore: Send + Sync {
+
+    /**
+    The current entry for ``key``.
+    */
+    fn get_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>>;
+
+    /**
+    Replace the current entry for ``key``. With ``keep_previous`` the replaced entry, if
+    any, becomes the previous entry.
+    */
+    fn put_entry(&self, key: &[u8], entry: &CacheEntry, keep_previous: bool) -> Result<()>;
+
+    /**
+    The entry last replaced with ``keep_previous``.
+    */
+    fn get_previous(&self, key: &[u8]) -> Result<Option<CacheEntry>>;
+
+    /**
+    Remove the current entry for ``key``, returning it.
+    */
+    fn remove_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>>;
+
+    /**
+    Every current entry with its key.
+    */
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<(IVec, CacheEntry)>> + '_>;
+
+    /**
+    Make earlier writes durable.
+    */
+    fn flush_entries(&self) -> Result<()> { Ok(()) }
+
+    /**
+    Tells stores apart, so that a fetch under way is only shared by requests cached in
+    the same store. Stores in use at the same time must give different ids. The default
+    is the address of the value, which no clone shares, so a store that can be cloned
+    should override it with the address of something its clones share, such as an
+    ``Arc``. Otherwise [`send_request`](crate::send_request), which clones the store for
+    each call, never shares a fetch.
+    */
+    fn store_id(&self) -> usize { (self as *const Self).cast::<()>() as usize }
+}
+
+/**
+The sled tree holding metadata, keyed the same as the response bodies in the default
+tree.
+*/
+pub(crate) const META_TREE: &str = "fred_api_meta";
+
+/**
+The sled trees holding the bodies and metadata of entries replaced by a refresh.
+*/
+pub(crate) const PREVIOUS_TREE: &str = "fred_api_previous";
+pub(crate) const PREVIOUS_META_TREE: &str = "fred_api_previous_meta";
+
+fn open_tree(db: &Db, name: &str) -> Result<Tree> {
+    db.open_tree(name).map_err(|e| Error::Cache(src!("{e}")))
+}
+
+fn read_tree_entry(
+    key: &[u8],
+    body_tree: &Tree,
+    meta_tree: &Tree) -> Result<Option<CacheEntry>>
+{
+    let Some(body) = body_tree.get(key).map_err(|e| Error::Cache(src!("{e}")))? else {
+        return Ok(None);
+    };
+    let meta = match meta_tree.get(key).map_err(|e| Error::Cache(src!("{e}")))? {
+        Some(bytes) => Some(EntryMeta::from_bytes(&bytes)?),
+        None => None,
+    };
+    Ok(Some(CacheEntry { body, meta }))
+}
+
+/**
+Bodies are kept in the default tree as they always have been, so caches written by
+earlier versions still read. Metadata and previous entries are kept in trees of their
+own, and each write is one transaction across them.
+*/
+impl CacheStore for Db {
+    fn get_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        read_tree_entry(key, self, &open_tree(self, META_TREE)?)
+    }
+
+    fn put_entry(&self, key: &[u8], entry: &CacheEntry, keep_previous: bool) -> Result<()> {
+        let meta_tree = open_tree(self, META_TREE)?;
+        let prev_tree = open_tree(self, PREVIOUS_TREE)?;
+        let prev_meta_tree = open_tree(self, PREVIOUS_META_TREE)?;
+        let meta = entry.meta.as_ref().map(EntryMeta::to_bytes);
+        (&**self, &meta_tree, &prev_tree, &prev_meta_tree)
+            .transaction(|(body_tx, meta_tx, prev_tx, prev_meta_tx)| {
+                let old_body = body_tx.insert(key, entry.body.clone())?;
+                let old_meta = match &meta {
+                    Some(meta) => meta_tx.insert(key, meta.as_slice())?,
+                    None => meta_tx.remove(key)?,
+                };
+                if let (true, Some(old_body)) = (keep_previous, old_body) {
+                    prev_tx.insert(key, old_body)?;
+                    match old_meta {
+                        Some(old_meta) => prev_meta_tx.insert(key, old_meta)?,
+                        None => prev_meta_tx.remove(key)?,
+                    };
+                }
+                Ok(())
+            })
+            .map_err(|e: TransactionError| Error::Cache(src!("{e}")))
+    }
+
+    fn get_previous(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        let prev_tree = open_tree(self, PREVIOUS_TREE)?;
+        read_tree_entry(key, &prev_tree, &open_tree(self, PREVIOUS_META_TREE)?)
+    }
+
+    fn remove_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        let meta_tree = open_tree(self, META_TREE)?;
+        let (body, meta) = (&**self, &meta_tree)
+            .transaction(|(body_tx, meta_tx)| {
+                Ok((body_tx.remove(key)?, meta_tx.remove(key)?))
+            })
+            .map_err(|e: TransactionError| Error::Cache(src!("{e}")))?;
+        let Some(body) = body else { return Ok(None) };
+        let meta = match meta {
+            Some(bytes) => Some(EntryMeta::from_bytes(&bytes)?),
+            None => None,
+        };
+        Ok(Some(CacheEntry { body, meta }))
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<(IVec, CacheEntry)>> + '_> {
+        let meta_tree = match open_tree(self, META_TREE) {
+            Ok(meta_tree) => meta_tree,
+            Err(e) => return Box::new(std::iter::once(Err(e))),
+        };
+        Box::new(Tree::iter(self).map(move |item| {
+            let (key, body) = item.map_err(|e| Error::Cache(src!("{e}")))?;
+            let meta = match meta_tree.get(&key).map_err(|e| Error::Cache(src!("{e}")))? {
+                Some(bytes) => Some(EntryMeta::from_bytes(&bytes)?),
+                None => None,
+            };
+            Ok((key, CacheEntry { body, meta }))
+        }))
+    }
+
+    fn flush_entries(&self) -> Result<()> {
+        Tree::flush(self).map(|_| ()).map_err(|e| Error::Cache(src!("{e}")))
+    }
+
+    // The state behind the default tree, which clones of the `Db` share.
+    fn store_id(&self) -> usize { std::ptr::from_ref(&***self) as usize }
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    current: BTreeMap<IVec, CacheEntry>,
+    previous: BTreeMap<IVec, CacheEntry>,
+}
+
+/**
+A store in memory, for tests and short-lived programs. Clones share the entries.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct MemoryStore(Arc<Mutex<Entries>>);
+
+impl MemoryStore {
+    pub fn new() -> Self { MemoryStore::default() }
+
+    pub fn len(&self) -> usize { self.0.lock().unwrap().current.len() }
+
+    pub fn is_empty(&self) -> bool { self.len() == 0 }
+}
+
+impl CacheStore for MemoryStore {
+    fn get_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        Ok(self.0.lock().unwrap().current.get(key).cloned())
+    }
+
+    fn put_entry(&self, key: &[u8], entry: &CacheEntry, keep_previous: bool) -> Result<()> {
+        let mut entries = self.0.lock().unwrap();
+        let old = entries.current.insert(key.into(), entry.clone());
+        if let (true, Some(old)) = (keep_previous, old) {
+            entries.previous.insert(key.into(), old);
+        }
+        Ok(())
+    }
+
+    fn get_previous(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        Ok(self.0.lock().unwrap().previous.get(key).cloned())
+    }
+
+    fn remove_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        Ok(self.0.lock().unwrap().current.remove(key))
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<(IVec, CacheEntry)>> + '_> {
+        let entries: Vec<_> = self.0.lock().unwrap().current.clone().into_iter().collect();
+        Box::new(entries.into_iter().map(Ok))
+    }
+
+    fn store_id(&self) -> usize { Arc::as_ptr(&self.0) as usize }
+}
+
+// Distinguishes the temporary files of concurrent writes in one process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/**
+A store of plain files under a directory, which several processes can share. Each entry
+is one ``.entry`` file named by the SHA-256 of its key, holding the key, the metadata
+and the body, and replaced entries are kept under ``previous``. Files are written in
+full before being renamed into place, so readers see the old entry or the new one and
+never part of either.
+```
+use fred_api::{CacheEntry, CacheStore, FsStore};
+
+let dir = tempfile::TempDir::new().unwrap();
+let store = FsStore::new(dir.path()).unwrap();
+let entry = CacheEntry { body: "<series/>".into(), meta: None };
+store.put_entry(b"series?series_id=GNPCA&", &entry, false).unwrap();
+let (key, _) = store.entries().next().unwrap().unwrap();
+assert_eq!(key, "series?series_id=GNPCA&");
+```
+*/
+#[derive(Clone, Debug)]
+pub struct FsStore {
+    dir: PathBuf,
+    // Shared by clones, see `store_id`.
+    id: Arc<()>,
+}
+
+impl FsStore {
+
+    /**
+    A store in ``dir``, creating it if need be.
+    */
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(dir.join("previous")).map_err(|e| io_error(&dir, e))?;
+        Ok(FsStore { dir, id: Arc::new(()) })
+    }
+
+    pub fn dir(&self) -> &Path { &self.dir }
+
+    fn path(dir: &Path, key: &[u8]) -> PathBuf {
+        dir.join(format!("{}.entry", sha256_hex(key)))
+    }
+
+    fn read(dir: &Path, key: &[u8]) -> Result<Option<CacheEntry>> {
+        let path = Self::path(dir, key);
+        let Some(bytes) = read_file(&path)? else { return Ok(None) };
+        let (file_key, entry) = decode_entry(&path, &bytes)?;
+        // Another key with the same hash is a miss.
+        Ok((file_key == key).then_some(entry))
+    }
+
+    fn write(dir: &Path, key: &[u8], entry: &CacheEntry) -> Result<()> {
+        write_file(&Self::path(dir, key), &encode_entry(key, entry))
+    }
+}
+
+impl CacheStore for FsStore {
+    fn get_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        Self::read(&self.dir, key)
+    }
+
+    fn put_entry(&self, key: &[u8], entry: &CacheEntry, keep_previous: bool) -> Result<()> {
+        if keep_previous {
+            if let Some(old) = Self::read(&self.dir, key)? {
+                Self::write(&self.dir.join("previous"), key, &old)?;
+            }
+        }
+        Self::write(&self.dir, key, entry)
+    }
+
+    fn get_previous(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        Self::read(&self.dir.join("previous"), key)
+    }
+
+    fn remove_entry(&self, key: &[u8]) -> Result<Option<CacheEntry>> {
+        let entry = Self::read(&self.dir, key)?;
+        if entry.is_some() {
+            remove_file(&Self::path(&self.dir, key))?;
+        }
+        Ok(entry)
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = Result<(IVec, CacheEntry)>> + '_> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) => return Box::new(std::iter::once(Err(io_error(&self.dir, e)))),
+        };
+        Box::new(dir.filter_map(move |item| {
+            let path = match item {
+                Ok(item) => item.path(),
+                Err(e) => return Some(Err(io_error(&self.dir, e))),
+            };
+            if path.extension()? != "entry" {
+                return None;
+            }
+            // Entries removed since the directory was listed are left out.
+            let bytes = read_file(&path).transpose()?;
+            Some(bytes.and_then(|bytes| {
+                let (key, entry) = decode_entry(&path, &bytes)?;
+                Ok((key.into(), entry))
+            }))
+        }))
+    }
+
+    fn store_id(&self) -> usize { Arc::as_ptr(&self.id) as usize }
+}
+
+/*
+The SHA-256 of `bytes` in hex, which names the files of a key.
+*/
+// test: fs_store_names_files_by_hash
+pub(crate) fn sha256_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(64);
+    for b in digest(&SHA256, bytes).as_ref() {
+        let _ = write!(hex, "{b:02x}");
+    }
+    hex
+}
+
+/*
+An entry file: a line with the lengths of the key and of the metadata, or `-` without
+metadata, then the key, the metadata and the body.
+*/
+// test: fs_store_names_files_by_hash
+fn encode_entry(key: &[u8], entry: &CacheEntry) -> Vec<u8> {
+    let meta = entry.meta.as_ref().map(EntryMeta::to_bytes);
+    let meta_len = meta.as_ref().map_or("-".to_string(), |meta| meta.len().to_string());
+    let mut bytes = format!("{} {meta_len}\n", key.len()).into_bytes();
+    bytes.extend_from_slice(key);
+    bytes.extend_from_slice(meta.as_deref().unwrap_or_default());
+    bytes.extend_from_slice(&entry.body);
+    bytes
+}
+
+// The key and entry of an entry file read from `path`.
+fn decode_entry(path: &Path, bytes: &[u8]) -> Result<(Vec<u8>, CacheEntry)> {
+    let bad = || Error::Cache(src!("'{}' is not a cache entry", path.display()));
+    let newline = bytes.iter().position(|&b| b == b'\n').ok_or_else(bad)?;
+    let header = std::str::from_utf8(&bytes[..newline]).map_err(|_| bad())?;
+    let (key_len, meta_len) = header.split_once(' ').ok_or_else(bad)?;
+    let key_len = key_len.parse().map_err(|_| bad())?;
+    let (key, rest) = bytes[newline + 1..].split_at_checked(key_len).ok_or_else(bad)?;
+    let (meta, body) = match meta_len {
+        "-" => (None, rest),
+        meta_len => {
+            let meta_len = meta_len.parse().map_err(|_| bad())?;
+            let (meta, body) = rest.split_at_checked(meta_len).ok_or_else(bad)?;
+            (Some(EntryMeta::from_bytes(meta)?), body)
+        }
+    };
+    Ok((key.to_vec(), CacheEntry { body: body.into(), meta }))
+}
+
+pub(crate) fn io_error(path: &Path, e: io::Error) -> Error {
+    Error::Cache(src!("'{}': {e}", path.display()))
+}
+
+pub(crate) fn read_file(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(io_error(path, e)),
+    }
+}
+
+pub(crate) fn write_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    let n = TEMP_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let temp = path.with_extension(format!("tmp{}-{n}", std::process::id()));
+    fs::write(&temp, bytes).map_err(|e| io_error(&temp, e))?;
+    fs::rename(&temp, path).map_err(|e| io_error(path, e))
+}
+
+pub(crate) fn remove_file(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(io_error(path, e)),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::{decode_entry, encode_entry, sha256_hex},
+        crate::*,
+        std::time::{Duration, UNIX_EPOCH},
+        std::fs,
+        tempfile::TempDir,
+    };
+
+    fn check_store(store: &dyn CacheStore) {
+        let key = b"series?series_id=GNPCA&";
+        let meta = EntryMeta {
+            fetched_at: UNIX_EPOCH + Duration::from_millis(1_760_000_000_123),
+            ..EntryMeta::new(200, 2)
+        };
+        let v1 = CacheEntry { body: "v1".into(), meta: Some(meta) };
+        let v2 = CacheEntry { body: "v2".into(), meta: None };
+        assert!(store.get_entry(key).unwrap().is_none());
+
+        store.put_entry(key, &v1, true).unwrap();
+        assert!(store.get_previous(key).unwrap().is_none());
+        store.put_entry(key, &v2, true).unwrap();
+        assert_eq!(store.get_entry(key).unwrap(), Some(v2.clone()));
+        assert_eq!(store.get_previous(key).unwrap(), Some(v1.clone()));
+        store.put_entry(key, &v1, false).unwrap();
+        assert_eq!(store.get_previous(key).unwrap(), Some(v1.clone()));
+
+        store.put_entry(b"tags?", &v2, false).unwrap();
+        let mut entries = store.entries().collect::<Result<Vec<_>>>().unwrap();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(entries, vec![(key.into(), v1.clone()), ("tags?".into(), v2)]);
+
+        assert_eq!(store.remove_entry(key).unwrap(), Some(v1));
+        assert!(store.get_entry(key).unwrap().is_none());
+        assert!(store.remove_entry(key).unwrap().is_none());
+        assert_eq!(store.entries().count(), 1);
+        store.flush_entries().unwrap();
+    }
+
+    #[test]
+    fn stores_behave_alike() {
+        let dir = TempDir::new().unwrap();
+        check_store(&sled::open(dir.path().join("sled")).unwrap());
+        check_store(&MemoryStore::new());
+        check_store(&FsStore::new(dir.path().join("fs")).unwrap());
+        check_store(&SnapshotStore::new(dir.path().join("snapshot")).unwrap());
+        check_store(&SqliteStore::open(dir.path().join("fred.sqlite")).unwrap());
+    }
+
+    #[test]
+    fn fs_store_names_files_by_hash() {
+        // Keys too long for a file name are fine.
+        let ids = vec!["GNPCA"; 60].join(";");
+        let key = format!("series/observations?series_id={ids}&units=pch&").into_bytes();
+        let dir = TempDir::new().unwrap();
+        let meta = EntryMeta { fetched_at: UNIX_EPOCH, ..EntryMeta::new(200, 9) };
+        let entry = CacheEntry { body: "<series/>".into(), meta: Some(meta) };
+        FsStore::new(dir.path()).unwrap().put_entry(&key, &entry, false).unwrap();
+        let path = dir.path().join(format!("{}.entry", sha256_hex(&key)));
+        let file = decode_entry(&path, &fs::read(&path).unwrap()).unwrap();
+        assert_eq!(file, (key.clone(), entry.clone()));
+
+        // Two stores on one directory see each other's entries.
+        let store = FsStore::new(dir.path()).unwrap();
+        assert_eq!(store.get_entry(&key).unwrap(), Some(entry));
+        assert_eq!(store.entries().map(|item| item.unwrap().0).collect::<Vec<_>>(), [key]);
+
+        let plain = CacheEntry { body: "-\n1 2".into(), meta: None };
+        let bytes = encode_entry(b"k", &plain);
+        assert_eq!(bytes, b"1 -\nk-\n1 2");
+        assert_eq!(decode_entry(&path, &bytes).unwrap(), (b"k".to_vec(), plain));
+        assert!(matches!(decode_entry(&path, b"9 -\nk"), Err(Error::Cache(_))));
+    }
+}
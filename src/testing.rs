@@ -0,0 +1,342 @@
+/*!
+An in-process HTTP server on localhost that replays recorded FRED responses, so that
+requests can be tested without a network. Enable with the ``test-util`` feature.
+```
+# #[cfg(feature = "test-util")]
+# tokio_test::block_on(async {
+use fred_api::{testing::MockServer, FredClient, Lookup};
+
+let server = MockServer::start().await.unwrap();
+server.insert("series/observations?series_id=GNPCA&", 200, r#"<observations/>"#);
+server.insert_error("series/observations?series_id=BAD&", 400, "Bad Request.");
+
+let dir = tempfile::TempDir::new().unwrap();
+let db = sled::open(dir.path()).unwrap();
+let client = FredClient::new(db, Some("abcd")).unwrap()
+    .with_base_uri(&server.base_uri()).unwrap()
+    .allow_http(true).unwrap();
+
+assert!(client.send(&client.request("series/observations?series_id=GNPCA&").unwrap(),
+    Lookup::FredOnCacheMiss).await.is_ok());
+assert!(client.get("series/observations?series_id=BAD&").await.is_err());
+assert_eq!(server.hits(), 2);
+# });
+```
+*/
+
+use {
+    crate::{canonical_mid_part, src, DebugErr, Error, FieldIter, Result},
+    http::{Request, Response, StatusCode},
+    http_body_util::Full,
+    hyper::{body::{Bytes, Incoming}, server::conn::http1, service::service_fn},
+    hyper_util::rt::TokioIo,
+    std::{
+        convert::Infallible,
+        fs,
+        net::SocketAddr,
+        path::Path,
+        sync::{Arc, Mutex},
+    },
+    tokio::{net::TcpListener, task::JoinHandle},
+};
+
+/**
+A recorded response and the request it answers.
+*/
+#[derive(Clone, Debug)]
+struct Route {
+    path: String,
+    params: Vec<String>,
+    status: u16,
+    body: Bytes,
+}
+
+impl Route {
+    fn new(mid_part: &str, status: u16, body: Bytes) -> Self {
+        let (path, params) = split_canonical(mid_part);
+        Route { path, params, status, body }
+    }
+
+    // A route matches when the paths agree and all of its parameters were requested.
+    fn matches(&self, path: &str, params: &[String]) -> bool {
+        self.path == path && self.params.iter().all(|p| params.contains(p))
+    }
+}
+
+// Canonical path and ``name=value`` parameters, without ``api_key``.
+fn split_canonical(mid_part: &str) -> (String, Vec<String>) {
+    let canonical = canonical_mid_part(mid_part);
+    let (path, query) = canonical.split_once('?').unwrap_or((&canonical, ""));
+    let params = query.split('&').filter(|p| !p.is_empty()).map(|p| p.to_string()).collect();
+    (path.to_string(), params)
+}
+
+/**
+FRED's error body, ``<error code="400" message="..."/>``.
+*/
+pub fn error_body(status: u16, message: &str) -> String {
+    let message = message
+        .replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\" ?>\n<error code=\"{status}\" message=\"{message}\"/>"
+    )
+}
+
+// The status of a recorded body, taken from the code of an `<error>` element.
+fn status_of(body: &Bytes) -> u16 {
+    FieldIter::new("error", vec!["code"], body.as_ref().into())
+        .next()
+        .and_then(|fields| fields.ok())
+        .and_then(|fields| fields[0].parse().ok())
+        .unwrap_or(200)
+}
+
+#[derive(Default)]
+struct State {
+    routes: Vec<Route>,
+    queued: Vec<Route>,
+    requests: Vec<String>,
+}
+
+/**
+A server on ``127.0.0.1`` answering requests under ``/fred`` from recorded responses.
+Requests are matched on their canonical form, ignoring ``api_key``. The most specific
+recorded request whose parameters were all requested answers, so a response recorded
+for ``series/observations?`` answers every ``series/observations`` request that has no
+better match. Responses added with [`MockServer::queue`] answer once, ahead of the others.
+Unmatched requests get a 404 error body. The server stops when dropped.
+*/
+pub struct MockServer {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+    handle: JoinHandle<()>,
+}
+
+impl MockServer {
+
+    /**
+    Starts a server without responses.
+    */
+    // test: mock_server_matches_most_specific_route
+    pub async fn start() -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| Error::Transport {
+                attempts: 1,
+                src: src!("Failed to bind mock server: {e}"),
+            })?;
+        let addr = listener.local_addr()
+            .map_err(|e| Error::Transport { attempts: 1, src: src!("{e}") })?;
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let server_state = state.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let state = server_state.clone();
+                tokio::spawn(async move {
+                    let service = service_fn(move |req| respond(req, state.clone()));
+                    let _ = http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service)
+                        .await;
+                });
+            }
+        });
+        Ok(MockServer { addr, state, handle })
+    }
+
+    /**
+    Starts a server answering from the files in ``dir``. A file's path relative to
+    ``dir``, less its extension, is the request it answers. When the last component
+    contains ``=`` it is the query, so
+    ```text
+    dir/series/observations/series_id=GNPCA&units=pch.xml
+    dir/series/observations.xml
+    ```
+    answer ``series/observations?series_id=GNPCA&units=pch&`` and any other
+    ``series/observations`` request. Bodies that are FRED ``<error>`` elements are served
+    with the status in their ``code``.
+    */
+    // test: mock_server_from_dir_works
+    pub async fn from_dir(dir: impl AsRef<Path>) -> Result<Self> {
+        let server = MockServer::start().await?;
+        let mut stack = vec![dir.as_ref().to_path_buf()];
+        while let Some(path) = stack.pop() {
+            let entries = fs::read_dir(&path)
+                .map_err(|e| Error::Config(src!("Failed to read '{}': {e}", path.display())))?;
+            for entry in entries {
+                let path = entry.map_err(|e| Error::Config(src!("{e}")))?.path();
+                if path.is_dir() {
+                    stack.push(path);
+                    continue;
+                }
+                let rel = path.strip_prefix(dir.as_ref())
+                    .map_err(|e| Error::Config(src!("{e}")))?;
+                let rel = rel.with_extension("");
+                let rel = rel.to_string_lossy().replace('\\', "/");
+                let mid_part = match rel.rsplit_once('/') {
+                    Some((path, query)) if query.contains('=') => format!("{path}?{query}"),
+                    _ if rel.contains('=') => Err(Error::Config(src!("No path for '{rel}'")))?,
+                    _ => format!("{rel}?"),
+                };
+                let body: Bytes = fs::read(&path)
+                    .map_err(|e| {
+                        Error::Config(src!("Failed to read '{}': {e}", path.display()))
+                    })?
+                    .into();
+                server.insert(&mid_part, status_of(&body), body);
+            }
+        }
+        Ok(server)
+    }
+
+    /**
+    Answer ``mid_part`` with ``status`` and ``body``, replacing an earlier response.
+    */
+    pub fn insert(&self, mid_part: &str, status: u16, body: impl Into<Bytes>) {
+        let route = Route::new(mid_part, status, body.into());
+        let mut state = self.state.lock().unwrap();
+        state.routes.retain(|r| r.path != route.path || r.params != route.params);
+        state.routes.push(route);
+    }
+
+    /**
+    Answer the next matching request with ``status`` and ``body``, once. Queued responses
+    are used in the order they were queued, for example to fail before succeeding.
+    */
+    // test: mock_server_queue_answers_once
+    pub fn queue(&self, mid_part: &str, status: u16, body: impl Into<Bytes>) {
+        let route = Route::new(mid_part, status, body.into());
+        self.state.lock().unwrap().queued.push(route);
+    }
+
+    /**
+    Answer ``mid_part`` with a FRED error body.
+    */
+    pub fn insert_error(&self, mid_part: &str, status: u16, message: &str) {
+        self.insert(mid_part, status, error_body(status, message));
+    }
+
+    /**
+    The base to pass to [`crate::FredClient::with_base_uri`].
+    */
+    pub fn base_uri(&self) -> String { format!("http://{}/fred", self.addr) }
+
+    pub fn addr(&self) -> SocketAddr { self.addr }
+
+    /**
+    The canonical mid-parts of the requests received so far, in order.
+    */
+    pub fn requests(&self) -> Vec<String> { self.state.lock().unwrap().requests.clone() }
+
+    pub fn hits(&self) -> usize { self.state.lock().unwrap().requests.len() }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) { self.handle.abort() }
+}
+
+async fn respond(
+    req: Request<Incoming>,
+    state: Arc<Mutex<State>>) -> std::result::Result<Response<Full<Bytes>>, Infallible>
+{
+    let path = req.uri().path().trim_start_matches("/fred");
+    let mid_part = format!("{}?{}", path, req.uri().query().unwrap_or(""));
+    let (path, params) = split_canonical(&mid_part);
+
+    let mut state = state.lock().unwrap();
+    state.requests.push(canonical_mid_part(&mid_part));
+    let queued = state.queued.iter().position(|route| route.matches(&path, &params));
+    let route = match queued {
+        Some(i) => Some(state.queued.remove(i)),
+        None => state.routes
+            .iter()
+            .filter(|route| route.matches(&path, &params))
+            .max_by_key(|route| route.params.len())
+            .cloned(),
+    };
+    let (status, body) = match route {
+        Some(route) => (route.status, route.body),
+        None => (404, error_body(404, "Not Found").into()),
+    };
+
+    let mut res = Response::new(Full::new(body));
+    *res.status_mut() = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use {
+        super::*,
+        http_body_util::{BodyExt, Empty},
+        hyper_util::{client::legacy::Client, rt::TokioExecutor},
+        tempfile::TempDir,
+    };
+
+    async fn get(server: &MockServer, mid_part: &str) -> (u16, String) {
+        let client: Client<_, Empty<Bytes>> = Client::builder(TokioExecutor::new())
+            .build_http();
+        let uri = format!("{}/{}api_key=abcd", server.base_uri(), mid_part).parse().unwrap();
+        let res = client.get(uri).await.unwrap();
+        let status = res.status().as_u16();
+        let body = res.into_body().collect().await.unwrap().to_bytes();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn mock_server_matches_most_specific_route() {
+        let server = MockServer::start().await.unwrap();
+        server.insert("series/observations?", 200, "any");
+        server.insert("series/observations?series_id=GNPCA&", 200, "gnpca");
+        server.insert_error("series?series_id=BAD&", 400, "Bad series_id");
+
+        assert_eq!(
+            get(&server, "series/observations?units=pch&series_id=GNPCA&").await,
+            (200, "gnpca".to_string()),
+        );
+        assert_eq!(
+            get(&server, "series/observations?series_id=UNRATE&").await,
+            (200, "any".to_string()),
+        );
+        let (status, body) = get(&server, "series?series_id=BAD&").await;
+        assert_eq!(status, 400);
+        assert!(body.contains(r#"<error code="400" message="Bad series_id"/>"#));
+        assert_eq!(get(&server, "tags?").await.0, 404);
+
+        assert_eq!(server.hits(), 4);
+        assert_eq!(server.requests()[0], "series/observations?series_id=GNPCA&units=pch&");
+    }
+
+    #[tokio::test]
+    async fn mock_server_queue_answers_once() {
+        let server = MockServer::start().await.unwrap();
+        server.insert("tags?", 200, "ok");
+        server.queue("tags?", 503, "first");
+        server.queue("tags?", 500, "second");
+
+        assert_eq!(get(&server, "tags?").await, (503, "first".to_string()));
+        assert_eq!(get(&server, "tags?").await, (500, "second".to_string()));
+        assert_eq!(get(&server, "tags?").await, (200, "ok".to_string()));
+    }
+
+    #[tokio::test]
+    async fn mock_server_from_dir_works() {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("series/observations")).unwrap();
+        fs::write(dir.path().join("series/observations/series_id=GNPCA.xml"), "gnpca").unwrap();
+        fs::write(dir.path().join("series/observations.xml"), "any").unwrap();
+        fs::write(dir.path().join("tags.xml"), error_body(500, "Internal Server Error"))
+            .unwrap();
+
+        let server = MockServer::from_dir(dir.path()).await.unwrap();
+        assert_eq!(
+            get(&server, "series/observations?series_id=GNPCA&").await,
+            (200, "gnpca".to_string()),
+        );
+        assert_eq!(get(&server, "series/observations?series_id=X&").await.1, "any");
+        assert_eq!(get(&server, "tags?").await.0, 500);
+    }
+}
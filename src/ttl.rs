@@ -0,0 +1,118 @@
+/*!
+How long a cached response stays fresh, by endpoint.
+*/
+
+use {
+    crate::RequestSpec,
+    std::time::Duration,
+};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/**
+Time-to-live of cached responses by endpoint path, used by ``Lookup::FredIfStale`` and
+``Lookup::StaleWhileRevalidate``. The defaults are
+
+| Endpoints | TTL |
+|---|---|
+| ``series/updates`` | 1 hour |
+| ``series/observations``, ``series/vintagedates``, ``release/tables``, ``releases/dates``, ``release/dates`` | 1 day |
+| searches and lists of series, such as ``series/search`` and ``category/series`` | 7 days |
+| other metadata, such as ``series``, ``category`` and ``tags`` | 30 days |
+```
+use {fred_api::{build_request, TtlPolicy}, std::time::Duration};
+
+let ttl = TtlPolicy::default().with_ttl("series/observations", Duration::from_secs(3600));
+let req = build_request("series/observations?series_id=GNPCA&", Some("abcd")).unwrap();
+assert_eq!(ttl.ttl(&req), Duration::from_secs(3600));
+```
+*/
+#[derive(Clone, Debug, PartialEq)]
+pub struct TtlPolicy {
+    default: Duration,
+    paths: Vec<(String, Duration)>,
+}
+
+impl TtlPolicy {
+
+    /**
+    Every endpoint has the same TTL.
+    */
+    pub fn uniform(ttl: Duration) -> Self {
+        TtlPolicy { default: ttl, paths: Vec::new() }
+    }
+
+    /**
+    Sets the TTL of one endpoint path, such as ``series/observations``.
+    */
+    pub fn with_ttl(mut self, path: &str, ttl: Duration) -> Self {
+        let path = path.trim_matches('/');
+        self.paths.retain(|(p, _)| p != path);
+        self.paths.push((path.to_string(), ttl));
+        self
+    }
+
+    /**
+    Sets the TTL of endpoints without their own.
+    */
+    pub fn with_default(mut self, ttl: Duration) -> Self {
+        self.default = ttl;
+        self
+    }
+
+    // test: ttl_policy_defaults_by_endpoint
+    pub fn ttl(&self, req: &RequestSpec) -> Duration {
+        let canonical = req.canonical();
+        let path = canonical.split_once('?').map_or(canonical.as_str(), |(path, _)| path);
+        self.paths
+            .iter()
+            .find(|(p, _)| p == path)
+            .map_or(self.default, |(_, ttl)| *ttl)
+    }
+}
+
+impl Default for TtlPolicy {
+    fn default() -> Self {
+        let mut policy = TtlPolicy::uniform(30 * DAY).with_ttl("series/updates", HOUR);
+        for path in [
+            "series/observations",
+            "series/vintagedates",
+            "release/tables",
+            "releases/dates",
+            "release/dates",
+        ] {
+            policy = policy.with_ttl(path, DAY);
+        }
+        for path in [
+            "series/search",
+            "series/search/tags",
+            "series/search/related_tags",
+            "category/series",
+            "release/series",
+            "tags/series",
+        ] {
+            policy = policy.with_ttl(path, 7 * DAY);
+        }
+        policy
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::*;
+
+    #[test]
+    fn ttl_policy_defaults_by_endpoint() {
+        let ttl = |mid_part: &str| {
+            TtlPolicy::default().ttl(&RequestSpec::new(mid_part, Some("abcd")).unwrap())
+        };
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        assert_eq!(ttl("series/observations?series_id=GNPCA&"), day);
+        assert_eq!(ttl("/series/observations?series_id=GNPCA"), day);
+        assert_eq!(ttl("series/updates?"), day / 24);
+        assert_eq!(ttl("tags/series?tag_names=food&"), 7 * day);
+        assert_eq!(ttl("series?series_id=GNPCA&"), 30 * day);
+        assert_eq!(ttl("category?category_id=125&"), 30 * day);
+    }
+}